@@ -0,0 +1,67 @@
+//! Measures how fast a large generated source file is lexed, both when the tokens are streamed
+//! and when they are all collected. Run it with
+//! `cargo run --release -p lexer --example throughput [functions]`.
+
+use lexer::lexing::Lexer;
+use lexer::source::{SourceFile, SourceId};
+use std::time::{Duration, Instant};
+
+/// Generates a file of functions that each have a doc comment, names of their own, number
+/// literals, an interpolated string and a plain comment
+fn generate(functions: usize) -> String {
+    (0..functions)
+        .map(|i| {
+            format!(
+                "/// function number {i}\n\
+                 public fn function_{i}(value: i32, other: imax) {{\n    \
+                 var total_{i}: imax = value * {i} + other;\n    \
+                 if total_{i} >= 0x{i:x} {{ return \"result ${{total_{i}}} done\"; }}\n    \
+                 val ratio = 2.5e3 / 1_000.0; // scale\n\
+                 }}\n",
+                i = i
+            )
+        })
+        .collect()
+}
+
+/// Runs `lex` a few times, and gets the fastest time it took and the number of tokens it lexed
+fn fastest<F: FnMut() -> usize>(mut lex: F) -> (Duration, usize) {
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            let tokens = lex();
+            (start.elapsed(), tokens)
+        })
+        .min()
+        .expect("Ran at least once")
+}
+
+fn main() {
+    let functions = std::env::args().nth(1).map_or(60_000, |arg| {
+        arg.parse().expect("Expected a number of functions")
+    });
+    let file = SourceFile::new(SourceId(0), "generated.src", generate(functions));
+
+    let (streamed, tokens) = fastest(|| Lexer::new(&file).count());
+    let (collected, _) = fastest(|| {
+        let tokens = Lexer::new(&file).tokenize_all();
+        tokens.expect("The generated file is valid").len()
+    });
+    let per_token = |time: Duration| time.as_nanos() as f64 / tokens as f64;
+    println!(
+        "{} lines, {} bytes, {} tokens",
+        file.get_text().lines().count(),
+        file.get_text().len(),
+        tokens
+    );
+    println!(
+        "streamed:  {:?} ({:.1} ns/token)",
+        streamed,
+        per_token(streamed)
+    );
+    println!(
+        "collected: {:?} ({:.1} ns/token)",
+        collected,
+        per_token(collected)
+    );
+}
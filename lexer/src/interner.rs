@@ -0,0 +1,147 @@
+use std::convert::TryInto;
+
+/// A handle to a string in an [`Interner`], which is cheap to copy and compare
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Stores each distinct string once, so that equal strings get the same [`Symbol`]. The strings
+/// are stored one after another in a single buffer, so interning a new string doesn't allocate
+/// unless the buffer has to grow.
+#[derive(Debug, Clone, Default)]
+pub struct Interner {
+    text: String,
+    /// Where the string of each symbol ends in `text`, as it starts where the last one ends
+    ends: Vec<usize>,
+    /// An open addressing table of the symbols, with a power of two length, at most half full
+    slots: Vec<Slot>,
+}
+
+/// An entry of the table of an [`Interner`], which keeps the high bits of the hash of its string
+/// so that probing and growing the table don't hash strings again
+#[derive(Debug, Copy, Clone, Default)]
+struct Slot {
+    hash: u32,
+    /// One more than the index of the symbol, or zero if the slot is empty
+    entry: u32,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, string: &str) -> Symbol {
+        if self.slots.is_empty() {
+            self.grow();
+        }
+        let hash = hash(string.as_bytes());
+        let index = match self.find(string, hash) {
+            Ok(symbol) => return symbol,
+            Err(index) => index,
+        };
+        let symbol = Symbol(self.ends.len() as u32);
+        self.text.push_str(string);
+        self.ends.push(self.text.len());
+        self.slots[index] = Slot {
+            hash,
+            entry: symbol.0 + 1,
+        };
+        if self.ends.len() * 2 > self.slots.len() {
+            self.grow();
+        }
+        symbol
+    }
+
+    /// Gets the symbol of a string, if it has been interned
+    pub fn get(&self, string: &str) -> Option<Symbol> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        self.find(string, hash(string.as_bytes())).ok()
+    }
+
+    /// Gets the string of a symbol from this interner
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        let index = symbol.index();
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        &self.text[start..self.ends[index]]
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    /// Finds the symbol of a string, or else the empty slot it would go in
+    fn find(&self, string: &str, hash: u32) -> Result<Symbol, usize> {
+        let mask = self.slots.len() - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            let slot = self.slots[index];
+            if slot.entry == 0 {
+                return Err(index);
+            }
+            let symbol = Symbol(slot.entry - 1);
+            if slot.hash == hash && self.resolve(symbol) == string {
+                return Ok(symbol);
+            }
+            index = (index + 1) & mask;
+        }
+    }
+
+    /// Doubles the size of the table, and moves every symbol into it
+    fn grow(&mut self) {
+        let length = (self.slots.len() * 2).max(64);
+        let old = std::mem::replace(&mut self.slots, vec![Slot::default(); length]);
+        for slot in old.into_iter().filter(|slot| slot.entry != 0) {
+            let mut index = slot.hash as usize & (length - 1);
+            while self.slots[index].entry != 0 {
+                index = (index + 1) & (length - 1);
+            }
+            self.slots[index] = slot;
+        }
+    }
+}
+
+/// A fast hash of a short string, as identifiers don't need protection from collision attacks.
+/// The high bits of the product are the best mixed, so those are kept.
+fn hash(bytes: &[u8]) -> u32 {
+    const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+    let mut hash = bytes.len() as u64;
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().expect("Chunks are 8 bytes"));
+        hash = (hash.rotate_left(5) ^ word).wrapping_mul(SEED);
+    }
+    let mut last = [0; 8];
+    last[..chunks.remainder().len()].copy_from_slice(chunks.remainder());
+    hash = (hash.rotate_left(5) ^ u64::from_le_bytes(last)).wrapping_mul(SEED);
+    (hash >> 32) as u32
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interner::Interner;
+
+    #[test]
+    fn equal_strings_share_symbols() {
+        let mut interner = Interner::new();
+        let a = interner.intern("alpha");
+        let b = interner.intern("beta");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("alpha"), a);
+        assert_eq!(interner.get("beta"), Some(b));
+        assert_eq!(interner.get("gamma"), None);
+        assert_eq!(interner.resolve(b), "beta");
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -1,431 +1,1020 @@
+use crate::interner::{Interner, Symbol};
+use crate::source::{SourceFile, SourceId};
 use crate::tokenization::TokenType::EOF;
 use crate::tokenization::{
-    Compound, Control, Declare, Literal, Loop, ObjectOrientation, Operator, Security, Structural,
-    Token, TokenType,
+    Doc, Fragment, Literal, Operator, Primitive, Structural, Token, TokenType,
 };
-use std::error::Error;
-use std::fmt::{Display, Formatter};
-use std::str::Chars;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
 
-pub struct Lexer {
-    filename: String,
-    string: Vec<char>,
+mod dialect;
+mod error;
+mod incremental;
+
+pub use dialect::*;
+pub use error::*;
+pub use incremental::*;
+
+/// Lexes the text of a source file, which it borrows
+pub struct Lexer<'a> {
+    source: SourceId,
+    filename: &'a str,
+    text: &'a str,
+    /// The byte offset of the current char in the text
     position: usize,
-    current: Option<char>,
     line_number: usize,
     column: usize,
+    /// Tokens that have been lexed by a peek, but not yet taken
+    lookahead: VecDeque<Result<Token, LexError>>,
+    /// Set once the `EOF` token has been lexed
+    finished: bool,
+    /// Whether comments that aren't doc comments are lexed into tokens
+    keep_comments: bool,
+    /// Whether tokens are lexed with the trivia around them
+    keep_trivia: bool,
+    dialect: Dialect,
+    interner: Interner,
+    /// A buffer that the text of a string literal is decoded into before it is interned
+    scratch: String,
+    /// The keyword each symbol is reserved as, indexed by symbol
+    reserved: Vec<Option<Keyword>>,
+    contextual: HashMap<Symbol, Keyword>,
 }
 
-#[derive(Debug)]
-pub struct LexError(&'static str);
-
-impl Display for LexError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
-    }
+/// A position in the input
+#[derive(Copy, Clone)]
+struct Mark {
+    line_number: usize,
+    column: usize,
+    position: usize,
 }
 
-impl Error for LexError {}
-
+/// Returns an error of the given kind, spanning from a mark to the current char
 macro_rules! lerr {
-    ($s:expr) => {
-        return Err(LexError($s));
+    ($lexer:expr, $start:expr, $kind:expr) => {
+        return Err($lexer.error($kind, $start))
     };
 }
 
-impl Lexer {
-    pub fn new(filename: String, input: String) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(file: &'a SourceFile) -> Self {
         let mut output = Lexer {
-            filename,
-            string: input.chars().collect::<Vec<char>>(),
+            source: file.get_id(),
+            filename: file.get_name(),
+            text: file.get_text(),
             position: 0,
-            current: None,
             line_number: 1,
             column: 1,
+            lookahead: VecDeque::new(),
+            finished: false,
+            keep_comments: false,
+            keep_trivia: false,
+            dialect: Dialect::standard(),
+            interner: Interner::new(),
+            scratch: String::new(),
+            reserved: vec![],
+            contextual: HashMap::new(),
         };
-        #[cfg(test)]
-        {
-            let take = output.take_current_char();
-            assert!(take == None)
-        }
-        output.current = output.string.get(0).map(|c| *c);
+        output.index_keywords();
 
         output
     }
 
+    /// Lexes plain comments into `Comment` tokens instead of skipping them
+    pub fn with_comments(mut self) -> Self {
+        self.keep_comments = true;
+        self
+    }
+
+    /// Lexes each token with its trivia, which is the whitespace and plain comments around it, so
+    /// that no input is lost. A token's trailing trivia runs to the end of its line, and the rest
+    /// is the leading trivia of the next token, so the full spans of the tokens cover the whole
+    /// input if it has no errors.
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
+    }
+
+    /// Lexes the keywords of a dialect instead of the standard ones
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self.index_keywords();
+        self
+    }
+
+    /// Interns identifiers into an existing interner, so that symbols are shared between files
+    pub fn with_interner(mut self, interner: Interner) -> Self {
+        self.interner = interner;
+        self.index_keywords();
+        self
+    }
+
+    pub fn get_dialect(&self) -> &Dialect {
+        &self.dialect
+    }
+
+    pub fn get_interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    pub fn into_interner(self) -> Interner {
+        self.interner
+    }
+
+    /// Gets the keyword an identifier token can be used as, if it is a contextual keyword
+    pub fn contextual(&self, token_type: &TokenType) -> Option<Keyword> {
+        match token_type {
+            TokenType::Identifier(symbol) => self.contextual.get(symbol).copied(),
+            _ => None,
+        }
+    }
+
+    /// Interns the words of the dialect, so that keywords are found by symbol
+    fn index_keywords(&mut self) {
+        let interner = &mut self.interner;
+        let reserved: Vec<_> = self
+            .dialect
+            .reserved_words()
+            .map(|(word, keyword)| (interner.intern(word), keyword))
+            .collect();
+        self.reserved.clear();
+        for (symbol, keyword) in reserved {
+            if self.reserved.len() <= symbol.index() {
+                self.reserved.resize(symbol.index() + 1, None);
+            }
+            self.reserved[symbol.index()] = Some(keyword);
+        }
+        self.contextual = self
+            .dialect
+            .contextual_words()
+            .map(|(word, keyword)| (interner.intern(word), keyword))
+            .collect();
+    }
+
+    /// Decodes the char starting at a byte offset
+    fn char_at(&self, position: usize) -> Option<char> {
+        match self.text.as_bytes().get(position) {
+            Some(&byte) if byte.is_ascii() => Some(byte as char),
+            Some(_) => self.text[position..].chars().next(),
+            None => None,
+        }
+    }
+
     /// Returns the current char, or None if there is none
-    fn current_char(&mut self) -> Option<char> {
-        self.current
+    fn current_char(&self) -> Option<char> {
+        self.char_at(self.position)
     }
 
-    /// Gets the current char, then moves to the next character
-    fn take_current_char(&mut self) -> Option<char> {
-        let output = self.current;
-        self.next_char();
-        output
+    /// Returns the char after the current one, or None if there is none
+    fn peek_char(&self) -> Option<char> {
+        self.current_char()
+            .and_then(|c| self.char_at(self.position + c.len_utf8()))
     }
 
     /// Moves the lexer up a character, and returns new current character
     fn next_char(&mut self) -> Option<char> {
-        if self.current.is_some() {
-            if self.current.unwrap() == '\n' {
+        if let Some(c) = self.current_char() {
+            if c == '\n' {
                 self.line_number += 1;
                 self.column = 1;
             } else {
                 self.column += 1;
             }
-            self.position += 1;
+            self.position += c.len_utf8();
+        }
+        self.current_char()
+    }
 
-            self.current = self.string.get(self.position).map(|c| *c);
+    /// Moves past chars until one doesn't match the predicate
+    fn skip_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while self.current_char().is_some_and(&predicate) {
+            self.next_char();
         }
-        self.current
     }
 
-    pub fn single_lex(&mut self) -> Result<Token, LexError> {
-        while match self.current_char() {
-            None => {
-                return Ok(Token::new(
-                    EOF,
-                    self.filename.clone(),
-                    self.line_number,
-                    self.column,
-                ))
+    /// Moves forward to a byte offset, which must be on the current line
+    fn advance_to(&mut self, position: usize) {
+        let skipped = &self.text.as_bytes()[self.position..position];
+        self.column += if skipped.is_ascii() {
+            skipped.len()
+        } else {
+            self.text[self.position..position].chars().count()
+        };
+        self.position = position;
+    }
+
+    /// Moves past whitespace, looking at bytes instead of chars where it can
+    fn skip_whitespace(&mut self) {
+        let bytes = self.text.as_bytes();
+        let mut position = self.position;
+        let mut line_number = self.line_number;
+        let mut column = self.column;
+        while let Some(&byte) = bytes.get(position) {
+            match byte {
+                b'\n' => {
+                    line_number += 1;
+                    column = 1;
+                    position += 1;
+                }
+                b' ' | b'\t' | b'\r' | b'\x0B' | b'\x0C' => {
+                    column += 1;
+                    position += 1;
+                }
+                _ if byte.is_ascii() => break,
+                _ => match self.text[position..].chars().next() {
+                    Some(c) if c.is_whitespace() => {
+                        column += 1;
+                        position += c.len_utf8();
+                    }
+                    _ => break,
+                },
             }
-            Some(c) => c.is_whitespace(),
-        } {
+        }
+        self.position = position;
+        self.line_number = line_number;
+        self.column = column;
+    }
+
+    /// Moves past the identifier chars from the current one
+    fn skip_identifier(&mut self) {
+        let bytes = self.text.as_bytes();
+        let mut end = self.position;
+        while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || bytes[end] == b'_') {
+            end += 1;
+        }
+        self.column += end - self.position;
+        self.position = end;
+        if bytes.get(end).is_some_and(|byte| !byte.is_ascii()) {
+            self.skip_while(|c| c.is_alphanumeric() || c == '_');
+        }
+    }
+
+    /// Moves back to a mark
+    fn reset(&mut self, mark: Mark) {
+        self.position = mark.position;
+        self.line_number = mark.line_number;
+        self.column = mark.column;
+    }
+
+    fn mark(&self) -> Mark {
+        Mark {
+            line_number: self.line_number,
+            column: self.column,
+            position: self.position,
+        }
+    }
+
+    /// Creates an error spanning from `start` to the current char. If nothing has been lexed since
+    /// `start`, the current char is skipped, so that lexing can continue after the error.
+    fn error(&mut self, kind: LexErrorKind, start: Mark) -> LexError {
+        if self.position == start.position {
             self.next_char();
         }
+        LexError::new(
+            kind,
+            self.filename.to_string(),
+            start.line_number,
+            start.column,
+            start.position..self.position,
+        )
+    }
 
-        match self.current_char() {
-            None => Err(LexError("Illegal End of Token Stream")),
-            Some(current) => {
-                let line = self.line_number;
-                let column = self.column;
-
-                if let Some(token_type) = match current {
-                    'a'..='z' | 'A'..='Z' | '_' => {
-                        let mut image = String::new();
-                        while let Some(char) = self.current_char() {
-                            if char.is_alphanumeric() {
-                                image.push(char);
-                            } else {
-                                break;
-                            }
-                            self.next_char();
-                        }
+    /// Lexes the entire input, ending with the `EOF` token, or gets every error in it
+    pub fn tokenize_all(&mut self) -> Result<Vec<Token>, Vec<LexError>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for next in self {
+            match next {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
 
-                        // Keywords here
-                        match image.as_ref() {
-                            "true" => Some(TokenType::Literal(Literal::Boolean(true))),
-                            "false" => Some(TokenType::Literal(Literal::Boolean(false))),
-                            "if" => Some(TokenType::Control(Control::If)),
-                            "else" => Some(TokenType::Control(Control::Else)),
-                            "break" => Some(TokenType::Control(Control::Break)),
-                            "return" => Some(TokenType::Control(Control::Return)),
-                            "try" => Some(TokenType::Control(Control::Try)),
-                            "catch" => Some(TokenType::Control(Control::Catch)),
-                            "case" => Some(TokenType::Control(Control::Case)),
-                            "while" => Some(TokenType::Loop(Loop::While)),
-                            "for" => Some(TokenType::Loop(Loop::For)),
-                            "do" => Some(TokenType::Loop(Loop::Do)),
-                            "private" => Some(TokenType::Security(Security::Private)),
-                            "internal" => Some(TokenType::Security(Security::Internal)),
-                            "public" => Some(TokenType::Security(Security::Public)),
-                            "struct" => Some(TokenType::Compound(Compound::Struct)),
-                            "call" => Some(TokenType::Compound(Compound::Call)),
-                            "trait" => Some(TokenType::Compound(Compound::Trait)),
-                            "enum" => Some(TokenType::Compound(Compound::Enum)),
-                            "fn" => Some(TokenType::Declare(Declare::Fn)),
-                            "val" => Some(TokenType::Declare(Declare::Val)),
-                            "var" => Some(TokenType::Declare(Declare::Var)),
-                            "this" => Some(TokenType::Object(ObjectOrientation::This)),
-                            "super" => Some(TokenType::Object(ObjectOrientation::Super)),
-                            "abstract" => Some(TokenType::Object(ObjectOrientation::Abstract)),
-                            "is" => Some(TokenType::Object(ObjectOrientation::Is)),
-                            "as" => Some(TokenType::Object(ObjectOrientation::As)),
-                            _ => Some(TokenType::Identifier(image)),
-                        }
-                    }
-                    '0'..='9' => {
-                        let mut base = 0_usize;
-                        while let Some(char) = self.current_char() {
-                            if char.is_digit(10) {
-                                let digit = char.to_digit(10).expect("Shouldn't fail, as checked");
-                                base *= 10;
-                                base += digit as usize;
-                            } else if char.is_whitespace() || char == '.' {
-                                break;
-                            } else {
-                                lerr!("Invalid number literal");
-                            }
-                            self.next_char();
-                        }
-                        if let Some(char) = self.current_char() {
-                            if char == '.' {
-                                let mut decimals = 0;
-                                let mut base = base as f64;
-                                while let Some(char) = self.current_char() {
-                                    if char.is_digit(10) {
-                                        let digit =
-                                            char.to_digit(10).expect("Shouldn't fail, as checked");
-                                        base *= 10.0;
-                                        base += digit as f64;
-                                        decimals += 1;
-                                    } else if char.is_whitespace() {
-                                        break;
-                                    } else {
-                                        lerr!("Invalid number literal");
-                                    }
-                                    self.next_char();
-                                }
-                                for _ in 0..decimals {
-                                    base /= 10.0;
-                                }
-                                Some(TokenType::Literal(Literal::Float(base)))
-                            } else {
-                                Some(TokenType::Literal(Literal::Integer(base)))
-                            }
-                        } else {
-                            None
-                        }
-                    }
-                    '\'' => {
-                        let c = self.next_char().expect("There must be a char after a '");
-                        if self.next_char() != Some('\'') {
-                            lerr!("There must be a following ' in a char literal")
-                        }
-                        Some(TokenType::Literal(Literal::Character(c)))
-                    }
-                    '"' => {
-                        self.next_char();
-                        let mut image = String::new();
-                        loop {
-                            match self.take_current_char() {
-                                Some('"') => {
-                                    break;
-                                }
-                                Some('\\') => {
-                                    let op = self.take_current_char().expect(
-                                        "There must a be character following a \\ in a string",
-                                    );
-                                    match op {
-                                        'n' => {
-                                            image.push('\n');
-                                        }
-                                        't' => {
-                                            image.push('\t');
-                                        }
-                                        _ => lerr!("Unsupported escape code"),
-                                    }
-                                }
-                                Some('\n') => lerr!("Strings must be on the same line"),
-                                Some(c) => {
-                                    image.push(c);
-                                }
-                                None => lerr!("Non-terminated String"),
-                            }
-                        }
-                        Some(TokenType::Identifier(image))
-                    }
-                    ';' => {
-                        self.next_char();
-                        Some(TokenType::Structural(Structural::Semicolon))
-                    }
-                    '{' => {
-                        self.next_char();
-                        Some(TokenType::Structural(Structural::LCurl))
-                    }
-                    '}' => {
-                        self.next_char();
-                        Some(TokenType::Structural(Structural::RCurl))
-                    }
-                    '=' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::Equal))
-                        } else {
-                            Some(TokenType::Operator(Operator::Assign))
-                        }
-                    }
-                    '!' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::NEqual))
-                        } else {
-                            Some(TokenType::Operator(Operator::Bang))
-                        }
-                    }
-                    '%' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Rem))
-                        } else {
-                            Some(TokenType::Operator(Operator::Rem))
-                        }
-                    }
-                    '&' => match self.next_char() {
-                        Some('=') => {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::And))
-                        }
-                        Some('&') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::Dand))
-                        }
-                        _ => Some(TokenType::Operator(Operator::And)),
-                    },
-                    '*' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Star))
-                        } else {
-                            Some(TokenType::Operator(Operator::Star))
-                        }
+    /// Gets the next token without consuming it
+    pub fn peek(&mut self) -> Option<&Result<Token, LexError>> {
+        self.peek_nth(0)
+    }
+
+    /// Gets the token `k` tokens ahead without consuming anything, where `peek_nth(0)` is the
+    /// token that `next` will return
+    pub fn peek_nth(&mut self, k: usize) -> Option<&Result<Token, LexError>> {
+        while self.lookahead.len() <= k {
+            let next = self.lex_next()?;
+            self.lookahead.push_back(next);
+        }
+        self.lookahead.get(k)
+    }
+
+    /// Lexes a token past the lookahead buffer, unless the stream has already ended
+    fn lex_next(&mut self) -> Option<Result<Token, LexError>> {
+        if self.finished {
+            return None;
+        }
+        Some(self.single_lex())
+    }
+
+    /// Lexes a single token, ignoring any tokens buffered by a peek. Once the input is exhausted,
+    /// this returns the `EOF` token forever.
+    ///
+    /// After an error, the lexer has skipped past the bad input, so lexing can continue. A file
+    /// too large for the positions of tokens is rejected with a single error instead.
+    #[inline]
+    pub fn single_lex(&mut self) -> Result<Token, LexError> {
+        if let Err(error) = check_length(self.text.len(), self.filename) {
+            self.finished = true;
+            return Err(error);
+        }
+        if !self.keep_trivia {
+            return self.lex_token();
+        }
+        let leading = self.position;
+        let token = self.lex_token()?;
+        Ok(self.trivia(token, leading))
+    }
+
+    /// Gives a token lexed from `leading` its trivia, if trivia is kept
+    fn trivia(&mut self, token: Token, leading: usize) -> Token {
+        if !self.keep_trivia {
+            return token;
+        }
+        let trailing = self.trailing_trivia();
+        token.with_trivia(leading, trailing)
+    }
+
+    /// Moves past the whitespace and plain comments after a token up to the end of its line, and
+    /// returns where they end
+    fn trailing_trivia(&mut self) -> usize {
+        let bytes = self.text.as_bytes();
+        loop {
+            match (bytes.get(self.position), bytes.get(self.position + 1)) {
+                (Some(b'\n'), _) | (Some(b'\r'), Some(b'\n')) | (None, _) => break,
+                (Some(b'/'), Some(b'/')) if !self.keep_comments => {
+                    // Doc comments are tokens of their own
+                    if self.doc_comment().is_some() {
+                        break;
                     }
-                    '+' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Plus))
-                        } else {
-                            Some(TokenType::Operator(Operator::Plus))
-                        }
+                    self.advance_to(self.line_end());
+                }
+                (Some(b'/'), Some(b'*')) if !self.keep_comments => {
+                    let start = self.mark();
+                    if self.block_comment(start).is_err() {
+                        // The unterminated comment is reported as the next token
+                        self.reset(start);
+                        break;
                     }
-                    ',' => {
+                }
+                _ => match self.current_char() {
+                    Some(c) if c.is_whitespace() => {
                         self.next_char();
-                        Some(TokenType::Operator(Operator::Comma))
                     }
-                    '-' => match self.next_char() {
-                        Some('=') => {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Minus))
-                        }
-                        Some('>') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::Arrow))
-                        }
-                        _ => Some(TokenType::Operator(Operator::Minus)),
-                    },
-                    '.' => {
-                        if self.next_char() == Some('.') {
-                            if self.next_char() == Some('.') {
-                                Some(TokenType::Operator(Operator::Ellipsis))
-                            } else {
-                                lerr!(".. is not a valid operator, needs to be either . or ...")
-                            }
-                        } else {
-                            Some(TokenType::Operator(Operator::Dot))
-                        }
-                    }
-                    '/' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::FwSlash))
-                        } else {
-                            Some(TokenType::Operator(Operator::FwSlash))
+                    _ => break,
+                },
+            }
+        }
+        self.position
+    }
+
+    fn lex_token(&mut self) -> Result<Token, LexError> {
+        let bytes = self.text.as_bytes();
+        loop {
+            self.skip_whitespace();
+            if bytes.get(self.position) != Some(&b'/') {
+                break;
+            }
+            let start = self.mark();
+            let token_type = match bytes.get(self.position + 1) {
+                Some(b'/') => self.line_comment(),
+                Some(b'*') => self.block_comment(start)?,
+                _ => break,
+            };
+            if let Some(token_type) = token_type {
+                return Ok(self.token(token_type, start));
+            }
+        }
+
+        let start = self.mark();
+        let current = match bytes.get(self.position) {
+            None => {
+                self.finished = true;
+                return Ok(self.token(EOF, start));
+            }
+            Some(&current) => current,
+        };
+        let token_type = match current {
+            b'r' if self.raw_string_hashes().is_some() => self.raw_string_literal(start)?,
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => {
+                self.skip_identifier();
+                let symbol = self
+                    .interner
+                    .intern(&self.text[start.position..self.position]);
+                match self.reserved.get(symbol.index()) {
+                    Some(Some(keyword)) => keyword.token_type(),
+                    _ => TokenType::Identifier(symbol),
+                }
+            }
+            b'0'..=b'9' => self.number(start)?,
+            b'\'' => self.char_literal(start)?,
+            b'"' => self.string_literal(start)?,
+            b'.' if bytes.get(self.position + 1) == Some(&b'.') => {
+                if bytes.get(self.position + 2) != Some(&b'.') {
+                    self.advance_to(self.position + 2);
+                    lerr!(self, start, LexErrorKind::IncompleteEllipsis)
+                }
+                self.advance_to(self.position + 3);
+                TokenType::Operator(Operator::Ellipsis)
+            }
+            _ => match operator(current, bytes.get(self.position + 1).copied()) {
+                Some((length, token_type)) => {
+                    self.advance_to(self.position + length);
+                    token_type
+                }
+                None => {
+                    let c = self.current_char().expect("Not at the end of the input");
+                    self.next_char();
+                    lerr!(self, start, LexErrorKind::UnsupportedCharacter(c))
+                }
+            },
+        };
+        Ok(self.token(token_type, start))
+    }
+
+    fn token(&self, token_type: TokenType, start: Mark) -> Token {
+        Token::new(
+            token_type,
+            self.source,
+            start.position..self.position,
+            start.line_number,
+            start.column,
+        )
+    }
+
+    /// Lexes a number literal, which may have a `0x`, `0o` or `0b` prefix, `_` separators, and a
+    /// primitive type suffix. Decimal literals may also have a fraction and an exponent.
+    fn number(&mut self, start: Mark) -> Result<TokenType, LexError> {
+        let radix = match (self.current_char(), self.peek_char()) {
+            (Some('0'), Some('x')) => 16,
+            (Some('0'), Some('o')) => 8,
+            (Some('0'), Some('b')) => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.next_char();
+            self.next_char();
+        }
+
+        let digits_start = self.position;
+        let count = self.digits(radix);
+        let mut float = false;
+        if radix == 10 {
+            if self.current_char() == Some('.')
+                && self.peek_char().is_some_and(|c| c.is_ascii_digit())
+            {
+                self.next_char();
+                self.digits(10);
+                float = true;
+            }
+            if let Some('e') | Some('E') = self.current_char() {
+                let sign = self.peek_char().filter(|&c| c == '+' || c == '-');
+                // The exponent and its sign are one byte each
+                let exponent = self.char_at(self.position + 1 + sign.map_or(0, |_| 1));
+                if exponent.is_some_and(|c| c.is_ascii_digit()) {
+                    self.advance_to(self.position + 1 + sign.map_or(0, |_| 1));
+                    self.digits(10);
+                    float = true;
+                }
+            }
+        }
+
+        if count == 0 || self.current_char().is_some_and(|c| c.is_ascii_digit()) {
+            self.skip_while(|c| c.is_alphanumeric() || c == '_');
+            lerr!(self, start, LexErrorKind::InvalidNumber)
+        }
+        let digits = &self.text[digits_start..self.position];
+        let digits: Cow<str> = if digits.contains('_') {
+            Cow::Owned(digits.replace('_', ""))
+        } else {
+            Cow::Borrowed(digits)
+        };
+        let suffix = if self
+            .current_char()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+        {
+            let suffix_start = self.position;
+            self.skip_identifier();
+            let suffix = &self.text[suffix_start..self.position];
+            match Primitive::from_name(suffix) {
+                Some(primitive) if primitive != Primitive::Char => Some(primitive),
+                _ => lerr!(self, start, LexErrorKind::InvalidSuffix(suffix.to_string())),
+            }
+        } else {
+            None
+        };
+
+        match suffix {
+            Some(Primitive::F32) | Some(Primitive::F64) => float = true,
+            Some(primitive) if float => lerr!(
+                self,
+                start,
+                LexErrorKind::InvalidSuffix(primitive.name().to_string())
+            ),
+            _ => {}
+        }
+        if float {
+            let value = if radix == 10 {
+                digits.parse::<f64>().expect("Only valid digits were taken")
+            } else {
+                match u64::from_str_radix(&digits, radix) {
+                    Ok(value) => value as f64,
+                    Err(_) => lerr!(self, start, LexErrorKind::NumberOutOfRange),
+                }
+            };
+            let in_range = match suffix {
+                Some(Primitive::F32) => (value as f32).is_finite(),
+                _ => value.is_finite(),
+            };
+            if !in_range {
+                lerr!(self, start, LexErrorKind::NumberOutOfRange)
+            }
+            Ok(TokenType::Literal(Literal::Float(value, suffix)))
+        } else {
+            let bits = match suffix {
+                Some(Primitive::I8) => 8,
+                Some(Primitive::I16) => 16,
+                Some(Primitive::I32) => 32,
+                _ => 64,
+            };
+            match u64::from_str_radix(&digits, radix) {
+                // The magnitude of the most negative value is allowed, so that it can be negated
+                Ok(value) if value <= 1 << (bits - 1) => {
+                    Ok(TokenType::Literal(Literal::Integer(value, suffix)))
+                }
+                _ => lerr!(self, start, LexErrorKind::NumberOutOfRange),
+            }
+        }
+    }
+
+    /// Moves past the digits of a number in the given radix and any `_` separators between them,
+    /// and returns how many digits there were
+    fn digits(&mut self, radix: u32) -> usize {
+        let bytes = self.text.as_bytes();
+        let mut end = self.position;
+        let mut count = 0;
+        while let Some(&byte) = bytes.get(end) {
+            if (byte as char).is_digit(radix) {
+                count += 1;
+            } else if byte != b'_' {
+                break;
+            }
+            end += 1;
+        }
+        self.advance_to(end);
+        count
+    }
+
+    /// Lexes a char literal, which holds one char or escape code
+    fn char_literal(&mut self, start: Mark) -> Result<TokenType, LexError> {
+        let c = match self.next_char() {
+            Some('\\') => self.escape(),
+            Some('\'') => {
+                self.next_char();
+                lerr!(self, start, LexErrorKind::EmptyChar)
+            }
+            Some(c) if c != '\n' => {
+                self.next_char();
+                Ok(c)
+            }
+            _ => lerr!(self, start, LexErrorKind::UnterminatedChar),
+        };
+        if self.current_char() != Some('\'') {
+            self.skip_while(|c| c != '\'' && c != '\n');
+            if self.current_char() == Some('\'') {
+                self.next_char();
+            }
+            lerr!(self, start, LexErrorKind::UnterminatedChar)
+        }
+        self.next_char();
+        Ok(TokenType::Literal(Literal::Character(c?)))
+    }
+
+    /// Lexes a string literal, which may span several lines. An invalid escape code doesn't end
+    /// the string, so lexing continues after its closing `"`.
+    ///
+    /// A string with `$name` or `${expression}` in it is interpolated, and is lexed into its text
+    /// and the tokens of each expression. A `$` followed by anything else is just text.
+    fn string_literal(&mut self, start: Mark) -> Result<TokenType, LexError> {
+        self.next_char();
+        // Nested strings decode into a buffer of their own
+        let mut image = std::mem::take(&mut self.scratch);
+        image.clear();
+        let mut fragments = vec![];
+        let mut error = None;
+        loop {
+            // Plain text is copied up to the next char that needs a closer look
+            let bytes = &self.text.as_bytes()[self.position..];
+            let run = bytes
+                .iter()
+                .position(|byte| matches!(byte, b'"' | b'\\' | b'$' | b'\n'))
+                .unwrap_or(bytes.len());
+            image.push_str(&self.text[self.position..self.position + run]);
+            self.advance_to(self.position + run);
+            let expression = match (self.current_char(), self.peek_char()) {
+                (Some('"'), _) => {
+                    self.next_char();
+                    break;
+                }
+                (Some('\\'), _) => {
+                    match self.escape() {
+                        Ok(c) => image.push(c),
+                        Err(e) => {
+                            error.get_or_insert(e);
                         }
                     }
-                    ':' => {
-                        if self.next_char() == Some(':') {
-                            Some(TokenType::Operator(Operator::Namespace))
-                        } else {
-                            Some(TokenType::Operator(Operator::Colon))
-                        }
+                    continue;
+                }
+                (Some('$'), Some('{')) => {
+                    self.next_char();
+                    self.next_char();
+                    self.interpolation(start)
+                }
+                (Some('$'), Some(c)) if c.is_ascii_alphabetic() || c == '_' => {
+                    self.next_char();
+                    self.lex_token().map(|token| vec![token])
+                }
+                (Some(c), _) => {
+                    self.next_char();
+                    image.push(c);
+                    continue;
+                }
+                (None, _) => lerr!(self, start, LexErrorKind::UnterminatedString),
+            };
+            match expression {
+                Ok(tokens) => {
+                    if !image.is_empty() {
+                        fragments.push(Fragment::Text(self.interner.intern(&image)));
+                        image.clear();
                     }
-                    '<' => match self.next_char() {
-                        Some('=') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::LessEqual))
-                        }
-                        Some('<') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::LShift))
-                        }
-                        _ => Some(TokenType::Operator(Operator::Less)),
-                    },
-                    '>' => match self.next_char() {
-                        Some('=') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::GreaterEqual))
-                        }
-                        Some('>') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::LShift))
-                        }
-                        _ => Some(TokenType::Operator(Operator::Greater)),
-                    },
-                    '^' => {
-                        if self.next_char() == Some('=') {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Xor))
-                        } else {
-                            Some(TokenType::Operator(Operator::Xor))
-                        }
+                    fragments.push(Fragment::Expression(tokens));
+                }
+                Err(e) if e.kind() == &LexErrorKind::UnterminatedString => return Err(e),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        let literal = match error {
+            Some(error) => Err(error),
+            None if fragments.is_empty() => Ok(Literal::String(self.interner.intern(&image))),
+            None => {
+                if !image.is_empty() {
+                    fragments.push(Fragment::Text(self.interner.intern(&image)));
+                }
+                Ok(Literal::Interpolated(fragments.into_boxed_slice()))
+            }
+        };
+        self.scratch = image;
+        literal.map(TokenType::Literal)
+    }
+
+    /// Lexes the tokens of a `${expression}` up to its matching `}`, which may have braces and
+    /// strings nested in it
+    fn interpolation(&mut self, start: Mark) -> Result<Vec<Token>, LexError> {
+        let mut tokens = vec![];
+        let mut error = None;
+        let mut depth = 0;
+        loop {
+            let leading = self.position;
+            // The closing brace is lexed without trivia, as the string continues after it
+            match self.lex_token() {
+                Ok(token) => match token.get_type() {
+                    TokenType::EOF => {
+                        // The end of the input is still ahead of the string
+                        self.finished = false;
+                        lerr!(self, start, LexErrorKind::UnterminatedString)
                     }
-                    '|' => match self.next_char() {
-                        Some('=') => {
-                            self.next_char();
-                            Some(TokenType::CompoundAssignment(Operator::Bar))
-                        }
-                        Some('|') => {
-                            self.next_char();
-                            Some(TokenType::Operator(Operator::Or))
+                    TokenType::Structural(Structural::RCurl) if depth == 0 => break,
+                    token_type => {
+                        match token_type {
+                            TokenType::Structural(Structural::LCurl) => depth += 1,
+                            TokenType::Structural(Structural::RCurl) => depth -= 1,
+                            _ => {}
                         }
-                        _ => Some(TokenType::Operator(Operator::Bar)),
-                    },
-                    '$' => {
-                        self.next_char();
-                        Some(TokenType::Operator(Operator::Dollar))
-                    }
-                    '(' => {
-                        self.next_char();
-                        Some(TokenType::Operator(Operator::LPar))
+                        tokens.push(self.trivia(token, leading));
                     }
-                    ')' => {
+                },
+                Err(e) if e.kind() == &LexErrorKind::UnterminatedString => return Err(e),
+                Err(e) => {
+                    error.get_or_insert(e);
+                }
+            }
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(tokens),
+        }
+    }
+
+    /// Gets how many `#`s the raw string starting at the current char has, if there is one
+    fn raw_string_hashes(&self) -> Option<usize> {
+        let rest = &self.text.as_bytes()[self.position + 1..];
+        let hashes = rest.iter().take_while(|&&byte| byte == b'#').count();
+        match rest.get(hashes) {
+            Some(b'"') => Some(hashes),
+            _ => None,
+        }
+    }
+
+    /// Lexes a raw string like `r#"..."#`, which has no escape codes and ends at a `"` followed by
+    /// as many `#`s as it started with
+    fn raw_string_literal(&mut self, start: Mark) -> Result<TokenType, LexError> {
+        let hashes = self
+            .raw_string_hashes()
+            .expect("Only called at a raw string");
+        for _ in 0..hashes + 2 {
+            self.next_char();
+        }
+        let text_start = self.position;
+        loop {
+            match self.current_char() {
+                Some('"')
+                    if self.text.as_bytes()[self.position + 1..]
+                        .iter()
+                        .take_while(|&&byte| byte == b'#')
+                        .count()
+                        >= hashes =>
+                {
+                    let symbol = self.interner.intern(&self.text[text_start..self.position]);
+                    for _ in 0..hashes + 1 {
                         self.next_char();
-                        Some(TokenType::Operator(Operator::RPar))
                     }
-                    '[' => {
-                        self.next_char();
-                        Some(TokenType::Operator(Operator::LBracket))
+                    return Ok(TokenType::Literal(Literal::String(symbol)));
+                }
+                Some(_) => {
+                    self.next_char();
+                }
+                None => lerr!(self, start, LexErrorKind::UnterminatedString),
+            }
+        }
+    }
+
+    /// Lexes an escape code starting at the current `\`
+    fn escape(&mut self) -> Result<char, LexError> {
+        let start = self.mark();
+        let code = self.next_char();
+        if code.is_some() {
+            self.next_char();
+        }
+        Ok(match code {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some('\\') => '\\',
+            Some('"') => '"',
+            Some('\'') => '\'',
+            Some('$') => '$',
+            Some('x') => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    match self.current_char().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => value = value * 16 + digit,
+                        None => lerr!(self, start, LexErrorKind::InvalidEscape),
                     }
-                    ']' => {
-                        self.next_char();
-                        Some(TokenType::Operator(Operator::RBracket))
+                    self.next_char();
+                }
+                if value > 0x7F {
+                    lerr!(self, start, LexErrorKind::InvalidEscape)
+                }
+                value as u8 as char
+            }
+            Some('u') => {
+                if self.current_char() != Some('{') {
+                    lerr!(self, start, LexErrorKind::InvalidEscape)
+                }
+                self.next_char();
+                let mut value = 0_u32;
+                let mut digits = 0;
+                while let Some(digit) = self.current_char().and_then(|c| c.to_digit(16)) {
+                    value = value.saturating_mul(16).saturating_add(digit);
+                    digits += 1;
+                    self.next_char();
+                }
+                if self.current_char() != Some('}') {
+                    lerr!(self, start, LexErrorKind::InvalidEscape)
+                }
+                self.next_char();
+                match std::char::from_u32(value) {
+                    Some(c) if (1..=6).contains(&digits) => c,
+                    _ => lerr!(self, start, LexErrorKind::InvalidEscape),
+                }
+            }
+            Some(c) if c != '\n' => lerr!(self, start, LexErrorKind::UnknownEscape(c)),
+            _ => lerr!(self, start, LexErrorKind::UnterminatedString),
+        })
+    }
+
+    /// Lexes a `//` comment, which is a doc comment if it starts with `///` or `//!`. A plain
+    /// comment is only lexed into a token if comments are kept.
+    fn line_comment(&mut self) -> Option<TokenType> {
+        let doc = self.doc_comment();
+        let start = self.position + if doc.is_some() { 3 } else { 2 };
+        let end = self.line_end();
+        self.advance_to(end);
+        if doc.is_none() && !self.keep_comments {
+            return None;
+        }
+        let text = self.interner.intern(&self.text[start..end]);
+        match doc {
+            Some(doc) => Some(TokenType::Doc(doc(text))),
+            None => Some(TokenType::Comment(text)),
+        }
+    }
+
+    /// Finds where the current line ends, before its `\n` or `\r\n`
+    fn line_end(&self) -> usize {
+        let bytes = self.text.as_bytes();
+        let end = bytes[self.position..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(bytes.len(), |length| self.position + length);
+        if end < bytes.len() && bytes[..end].ends_with(b"\r") {
+            end - 1
+        } else {
+            end
+        }
+    }
+
+    /// Gets the kind of doc comment the `//` comment at the current char is, if it is one
+    fn doc_comment(&self) -> Option<fn(Symbol) -> Doc> {
+        let bytes = self.text.as_bytes();
+        match (bytes.get(self.position + 2), bytes.get(self.position + 3)) {
+            (Some(b'/'), next) if next != Some(&b'/') => Some(Doc::Outer),
+            (Some(b'!'), _) => Some(Doc::Inner),
+            _ => None,
+        }
+    }
+
+    /// Lexes a `/* */` comment, which may have other block comments nested in it. It is only
+    /// lexed into a token if comments are kept.
+    fn block_comment(&mut self, start: Mark) -> Result<Option<TokenType>, LexError> {
+        let bytes = self.text.as_bytes();
+        let text_start = self.position + 2;
+        self.advance_to(text_start);
+        let mut depth = 1;
+        loop {
+            match (bytes.get(self.position), bytes.get(self.position + 1)) {
+                (None, _) => lerr!(self, start, LexErrorKind::UnterminatedComment),
+                (Some(b'/'), Some(b'*')) => {
+                    depth += 1;
+                    self.advance_to(self.position + 2);
+                }
+                (Some(b'*'), Some(b'/')) => {
+                    depth -= 1;
+                    self.advance_to(self.position + 2);
+                    if depth == 0 {
+                        break;
                     }
-                    _ => lerr!("Unsupported character"),
-                } {
-                    Ok(Token::new(token_type, self.filename.clone(), line, column))
-                } else {
-                    lerr!("No token created!")
+                }
+                _ => {
+                    self.next_char();
                 }
             }
         }
+        if !self.keep_comments {
+            return Ok(None);
+        }
+        let text = self
+            .interner
+            .intern(&self.text[text_start..self.position - 2]);
+        Ok(Some(TokenType::Comment(text)))
+    }
+}
+
+/// Checks that a source file of `len` bytes is small enough for the 32 bit positions of tokens
+fn check_length(len: usize, filename: &str) -> Result<(), LexError> {
+    if len > u32::MAX as usize {
+        return Err(LexError::new(
+            LexErrorKind::FileTooLarge,
+            filename.to_string(),
+            1,
+            1,
+            0..0,
+        ));
+    }
+    Ok(())
+}
+
+/// Gets the operator or structural token starting with a byte, given the byte after it, and how
+/// many bytes long it is
+fn operator(first: u8, second: Option<u8>) -> Option<(usize, TokenType)> {
+    use Operator::*;
+    let (length, token_type) = match (first, second) {
+        (b';', _) => (1, TokenType::Structural(Structural::Semicolon)),
+        (b'{', _) => (1, TokenType::Structural(Structural::LCurl)),
+        (b'}', _) => (1, TokenType::Structural(Structural::RCurl)),
+        (b'=', Some(b'=')) => (2, TokenType::Operator(Equal)),
+        (b'=', _) => (1, TokenType::Operator(Assign)),
+        (b'!', Some(b'=')) => (2, TokenType::Operator(NEqual)),
+        (b'!', _) => (1, TokenType::Operator(Bang)),
+        (b'%', Some(b'=')) => (2, TokenType::CompoundAssignment(Rem)),
+        (b'%', _) => (1, TokenType::Operator(Rem)),
+        (b'&', Some(b'=')) => (2, TokenType::CompoundAssignment(And)),
+        (b'&', Some(b'&')) => (2, TokenType::Operator(Dand)),
+        (b'&', _) => (1, TokenType::Operator(And)),
+        (b'*', Some(b'=')) => (2, TokenType::CompoundAssignment(Star)),
+        (b'*', _) => (1, TokenType::Operator(Star)),
+        (b'+', Some(b'=')) => (2, TokenType::CompoundAssignment(Plus)),
+        (b'+', _) => (1, TokenType::Operator(Plus)),
+        (b',', _) => (1, TokenType::Operator(Comma)),
+        (b'-', Some(b'=')) => (2, TokenType::CompoundAssignment(Minus)),
+        (b'-', Some(b'>')) => (2, TokenType::Operator(Arrow)),
+        (b'-', _) => (1, TokenType::Operator(Minus)),
+        (b'.', _) => (1, TokenType::Operator(Dot)),
+        (b'/', Some(b'=')) => (2, TokenType::CompoundAssignment(FwSlash)),
+        (b'/', _) => (1, TokenType::Operator(FwSlash)),
+        (b':', Some(b':')) => (2, TokenType::Operator(Namespace)),
+        (b':', _) => (1, TokenType::Operator(Colon)),
+        (b'<', Some(b'=')) => (2, TokenType::Operator(LessEqual)),
+        (b'<', Some(b'<')) => (2, TokenType::Operator(LShift)),
+        (b'<', _) => (1, TokenType::Operator(Less)),
+        (b'>', Some(b'=')) => (2, TokenType::Operator(GreaterEqual)),
+        (b'>', Some(b'>')) => (2, TokenType::Operator(RShift)),
+        (b'>', _) => (1, TokenType::Operator(Greater)),
+        (b'^', Some(b'=')) => (2, TokenType::CompoundAssignment(Xor)),
+        (b'^', _) => (1, TokenType::Operator(Xor)),
+        (b'|', Some(b'=')) => (2, TokenType::CompoundAssignment(Bar)),
+        (b'|', Some(b'|')) => (2, TokenType::Operator(Or)),
+        (b'|', _) => (1, TokenType::Operator(Bar)),
+        (b'$', _) => (1, TokenType::Operator(Dollar)),
+        (b'(', _) => (1, TokenType::Operator(LPar)),
+        (b')', _) => (1, TokenType::Operator(RPar)),
+        (b'[', _) => (1, TokenType::Operator(LBracket)),
+        (b']', _) => (1, TokenType::Operator(RBracket)),
+        _ => return None,
+    };
+    Some((length, token_type))
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Result<Token, LexError>;
+
+    /// Gets the next token, ending after the `EOF` token
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lookahead.pop_front() {
+            Some(token) => Some(token),
+            None => self.lex_next(),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::lexing::Lexer;
-    use crate::tokenization::{Declare, HasTokenType, TokenType};
+    use crate::interner::{Interner, Symbol};
+    use crate::lexing::{check_length, Dialect, Keyword, LexErrorKind, Lexer};
+    use crate::source::{SourceFile, SourceId};
+    use crate::tokenization::{
+        Control, Declare, Doc, Fragment, HasTokenType, Literal, ObjectOrientation, Operator,
+        Primitive, Structural, Token, TokenType,
+    };
+
+    /// Gets the identifier token of a name the lexer has interned
+    fn ident(lexer: &Lexer, name: &str) -> TokenType {
+        TokenType::Identifier(interned(lexer, name))
+    }
+
+    /// Gets the symbol of a string the lexer has interned
+    fn interned(lexer: &Lexer, string: &str) -> Symbol {
+        lexer
+            .get_interner()
+            .get(string)
+            .expect("String was not lexed")
+    }
 
     #[test]
     fn get_char() {
         let string = "a";
-        let mut lexer = Lexer::new("test".to_string(), string.to_string());
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let lexer = Lexer::new(&file);
         assert_eq!(lexer.current_char(), Some('a'))
     }
 
     #[test]
     fn lex_identifier() {
         let string = "   hello _hello3";
-        let mut lexer = Lexer::new("test".to_string(), string.to_string());
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
         assert_eq!(
             lexer.single_lex().unwrap().token_type(),
-            Some(&TokenType::Identifier("hello".to_string()))
+            Some(&ident(&lexer, "hello"))
         );
         assert_eq!(
             lexer.single_lex().unwrap().token_type(),
-            Some(&TokenType::Identifier("_hello3".to_string()))
+            Some(&ident(&lexer, "_hello3"))
         )
     }
 
@@ -433,17 +1022,467 @@ mod test {
     #[should_panic]
     fn incorrect_number_fails() {
         let string = "3a";
-        let mut lexer = Lexer::new("test".to_string(), string.to_string());
-        lexer.single_lex();
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        lexer.single_lex().unwrap();
     }
 
     #[test]
     fn lex() {
         let string = "var i: imax = 3.0 as imax;";
-        let mut lexer = Lexer::new("test".to_string(), string.to_string());
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
         assert_eq!(
             lexer.single_lex().unwrap().token_type(),
             Some(&TokenType::Declare(Declare::Var))
         );
     }
+
+    #[test]
+    fn iterates_until_eof() {
+        let string = "fn main() {}";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::Declare(Declare::Fn),
+                &ident(&lexer, "main"),
+                &TokenType::Operator(Operator::LPar),
+                &TokenType::Operator(Operator::RPar),
+                &TokenType::Structural(Structural::LCurl),
+                &TokenType::Structural(Structural::RCurl),
+                &TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_from_errors() {
+        let string = "a # b\nval s = \"\\q\" 3x '";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let errors = lexer.tokenize_all().unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|error| {
+                (
+                    error.kind().clone(),
+                    error.get_line_number(),
+                    error.get_column(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (LexErrorKind::UnsupportedCharacter('#'), 1, 3),
+                (LexErrorKind::UnknownEscape('q'), 2, 10),
+                (LexErrorKind::InvalidSuffix("x".to_string()), 2, 14),
+                (LexErrorKind::UnterminatedChar, 2, 17),
+            ]
+        );
+        assert_eq!(errors[1].get_span(), 15..17);
+    }
+
+    #[test]
+    fn lexing_continues_after_error() {
+        let string = "a # b";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        assert!(lexer.next().unwrap().is_ok());
+        assert!(lexer.next().unwrap().is_err());
+        assert_eq!(
+            lexer.next().unwrap().unwrap().token_type(),
+            Some(&ident(&lexer, "b"))
+        );
+        assert_eq!(lexer.next().unwrap().unwrap().get_type(), &TokenType::EOF);
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn peek_ahead() {
+        let string = "a b c";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut interner = Interner::new();
+        let a = TokenType::Identifier(interner.intern("a"));
+        let c = TokenType::Identifier(interner.intern("c"));
+        let mut lexer = Lexer::new(&file).with_interner(interner);
+        assert_eq!(
+            lexer.peek_nth(2).unwrap().as_ref().unwrap().token_type(),
+            Some(&c)
+        );
+        assert!(lexer.peek_nth(4).is_none());
+        assert_eq!(
+            lexer.peek().unwrap().as_ref().unwrap().token_type(),
+            Some(&a)
+        );
+        assert_eq!(lexer.next().unwrap().unwrap().token_type(), Some(&a));
+        assert_eq!(lexer.count(), 3);
+    }
+
+    #[test]
+    fn tokens_refer_to_source() {
+        let string = "val aé = a::b;\n  x >> 2";
+        let file = SourceFile::new(SourceId(3), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let spans: Vec<_> = tokens
+            .iter()
+            .map(|token| &string[token.get_span()])
+            .collect();
+        assert_eq!(
+            spans,
+            vec!["val", "aé", "=", "a", "::", "b", ";", "x", ">>", "2", ""]
+        );
+        assert!(tokens.iter().all(|token| token.get_source() == SourceId(3)));
+        assert_eq!(tokens[2].get_column(), 8);
+        assert_eq!(
+            tokens[4].get_type(),
+            &TokenType::Operator(Operator::Namespace)
+        );
+        assert_eq!(tokens[8].get_type(), &TokenType::Operator(Operator::RShift));
+        assert_eq!(
+            (tokens[8].get_line_number(), tokens[8].get_column()),
+            (2, 5)
+        );
+    }
+
+    #[test]
+    fn shared_interner() {
+        let first = SourceFile::new(SourceId(0), "first", "alpha beta");
+        let second = SourceFile::new(SourceId(1), "second", "beta alpha");
+        let mut lexer = Lexer::new(&first);
+        let first_tokens = lexer.tokenize_all().unwrap();
+        let mut lexer = Lexer::new(&second).with_interner(lexer.into_interner());
+        let second_tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(first_tokens[0].get_type(), second_tokens[1].get_type());
+        assert_eq!(first_tokens[1].get_type(), second_tokens[0].get_type());
+        assert_eq!(
+            lexer
+                .get_interner()
+                .resolve(match second_tokens[0].get_type() {
+                    TokenType::Identifier(symbol) => *symbol,
+                    token_type => panic!("{:?} is not an identifier", token_type),
+                }),
+            "beta"
+        );
+    }
+
+    #[test]
+    fn rejects_files_too_large() {
+        assert!(check_length(u32::MAX as usize, "test").is_ok());
+        let error = check_length(u32::MAX as usize + 1, "test").unwrap_err();
+        assert_eq!(error.kind(), &LexErrorKind::FileTooLarge);
+    }
+
+    #[test]
+    fn lossless() {
+        let string =
+            "/// doc\r\nfn a() { // plain\r\n\tval s = \"${ b } /* s */\"; /* x\n y */ c\n}\n\n";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file).with_trivia();
+        let tokens = lexer.tokenize_all().unwrap();
+        let text: String = tokens
+            .iter()
+            .map(|token| &string[token.get_full_span()])
+            .collect();
+        assert_eq!(text, string);
+
+        let trivia = |index: usize| {
+            (
+                &string[tokens[index].get_leading_trivia()],
+                &string[tokens[index].get_trailing_trivia()],
+            )
+        };
+        assert_eq!(
+            tokens[0].get_type(),
+            &TokenType::Doc(Doc::Outer(interned(&lexer, " doc")))
+        );
+        assert_eq!(trivia(1), ("\r\n", " "));
+        assert_eq!(trivia(5), ("", " // plain"));
+        assert!(matches!(
+            tokens[9].get_type(),
+            TokenType::Literal(Literal::Interpolated(fragments))
+                if fragments[1] == Fragment::Text(interned(&lexer, " /* s */"))
+        ));
+        assert_eq!(trivia(6), ("\r\n\t", " "));
+        assert_eq!(trivia(10), ("", " /* x\n y */ "));
+        assert_eq!(trivia(11), ("", ""));
+        assert_eq!(trivia(12), ("\n", ""));
+        assert_eq!(trivia(13), ("\n\n", ""));
+        assert_eq!(tokens[13].get_type(), &TokenType::EOF);
+    }
+
+    #[test]
+    fn doc_comments() {
+        let string = "/// outer\nfn a() { //! inner\n // plain\n //// plain /* a\n}";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::Doc(Doc::Outer(interned(&lexer, " outer"))),
+                &TokenType::Declare(Declare::Fn),
+                &ident(&lexer, "a"),
+                &TokenType::Operator(Operator::LPar),
+                &TokenType::Operator(Operator::RPar),
+                &TokenType::Structural(Structural::LCurl),
+                &TokenType::Doc(Doc::Inner(interned(&lexer, " inner"))),
+                &TokenType::Structural(Structural::RCurl),
+                &TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn kept_comments() {
+        let string = "a // one\r\n/* x /* y */ */ b / c";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file).with_comments();
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &ident(&lexer, "a"),
+                &TokenType::Comment(interned(&lexer, " one")),
+                &TokenType::Comment(interned(&lexer, " x /* y */ ")),
+                &ident(&lexer, "b"),
+                &TokenType::Operator(Operator::FwSlash),
+                &ident(&lexer, "c"),
+                &TokenType::EOF,
+            ]
+        );
+        assert_eq!(tokens[2].get_line_number(), 2);
+    }
+
+    #[test]
+    fn unterminated_block_comment() {
+        let string = "a /* /* */";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let errors = lexer.tokenize_all().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), &LexErrorKind::UnterminatedComment);
+        assert_eq!(errors[0].get_span(), 2..10);
+    }
+
+    #[test]
+    fn number_literals() {
+        let string = "3) 0xff_FF 0o17 0b1010i8 1_000 2.5f32 1e3 6.02E+23 7f64 128i8 3.foo";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::Literal(Literal::Integer(3, None)),
+                &TokenType::Operator(Operator::RPar),
+                &TokenType::Literal(Literal::Integer(0xffff, None)),
+                &TokenType::Literal(Literal::Integer(0o17, None)),
+                &TokenType::Literal(Literal::Integer(10, Some(Primitive::I8))),
+                &TokenType::Literal(Literal::Integer(1000, None)),
+                &TokenType::Literal(Literal::Float(2.5, Some(Primitive::F32))),
+                &TokenType::Literal(Literal::Float(1000.0, None)),
+                &TokenType::Literal(Literal::Float(6.02e23, None)),
+                &TokenType::Literal(Literal::Float(7.0, Some(Primitive::F64))),
+                &TokenType::Literal(Literal::Integer(128, Some(Primitive::I8))),
+                &TokenType::Literal(Literal::Integer(3, None)),
+                &TokenType::Operator(Operator::Dot),
+                &ident(&lexer, "foo"),
+                &TokenType::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_number_literals() {
+        let string = "129i8 99999999999999999999 0x 0b102 2.5i32 1e400 1e39f32 4char";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let errors: Vec<_> = lexer
+            .tokenize_all()
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.kind().clone())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                LexErrorKind::NumberOutOfRange,
+                LexErrorKind::NumberOutOfRange,
+                LexErrorKind::InvalidNumber,
+                LexErrorKind::InvalidNumber,
+                LexErrorKind::InvalidSuffix("i32".to_string()),
+                LexErrorKind::NumberOutOfRange,
+                LexErrorKind::NumberOutOfRange,
+                LexErrorKind::InvalidSuffix("char".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn string_literals() {
+        let string = r###""a\\\"\'\0\r\n\t" "\x41\u{1F600}" r"\n" r#"say "hi""# "two
+lines" '\'' '\u{e9}' name"###;
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &TokenType::Literal(Literal::String(interned(&lexer, "a\\\"'\0\r\n\t"))),
+                &TokenType::Literal(Literal::String(interned(&lexer, "A😀"))),
+                &TokenType::Literal(Literal::String(interned(&lexer, "\\n"))),
+                &TokenType::Literal(Literal::String(interned(&lexer, "say \"hi\""))),
+                &TokenType::Literal(Literal::String(interned(&lexer, "two\nlines"))),
+                &TokenType::Literal(Literal::Character('\'')),
+                &TokenType::Literal(Literal::Character('é')),
+                &ident(&lexer, "name"),
+                &TokenType::EOF,
+            ]
+        );
+        assert_eq!(tokens[5].get_line_number(), 2);
+    }
+
+    #[test]
+    fn invalid_string_literals() {
+        let string = r#"'' 'ab' '\q' "\x80" "\u{D800}" "\u41" r"open"#;
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let errors: Vec<_> = lexer
+            .tokenize_all()
+            .unwrap_err()
+            .into_iter()
+            .map(|error| error.kind().clone())
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                LexErrorKind::EmptyChar,
+                LexErrorKind::UnterminatedChar,
+                LexErrorKind::UnknownEscape('q'),
+                LexErrorKind::InvalidEscape,
+                LexErrorKind::InvalidEscape,
+                LexErrorKind::InvalidEscape,
+                LexErrorKind::UnterminatedString,
+            ]
+        );
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let string = r#""sum = ${a + b}, $name!" "${ {x} + "in${"ner"}" } \$a $1 $été""#;
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        let fragments = |index: usize| match tokens[index].get_type() {
+            TokenType::Literal(Literal::Interpolated(fragments)) => fragments,
+            token_type => panic!("{:?} is not interpolated", token_type),
+        };
+        fn types(fragment: &Fragment) -> Vec<&TokenType> {
+            match fragment {
+                Fragment::Expression(tokens) => tokens.iter().map(Token::get_type).collect(),
+                Fragment::Text(_) => vec![],
+            }
+        }
+
+        let first = fragments(0);
+        assert_eq!(first.len(), 5);
+        assert_eq!(first[0], Fragment::Text(interned(&lexer, "sum = ")));
+        assert_eq!(
+            types(&first[1]),
+            vec![
+                &ident(&lexer, "a"),
+                &TokenType::Operator(Operator::Plus),
+                &ident(&lexer, "b"),
+            ]
+        );
+        assert_eq!(first[2], Fragment::Text(interned(&lexer, ", ")));
+        assert_eq!(types(&first[3]), vec![&ident(&lexer, "name")]);
+        assert_eq!(first[4], Fragment::Text(interned(&lexer, "!")));
+        if let Fragment::Expression(tokens) = &first[3] {
+            assert_eq!(tokens[0].get_column(), 19);
+        }
+
+        let second = fragments(1);
+        assert_eq!(second.len(), 2);
+        let nested = types(&second[0]);
+        assert_eq!(nested.len(), 5);
+        assert!(matches!(
+            nested[4],
+            TokenType::Literal(Literal::Interpolated(inner)) if inner.len() == 2
+        ));
+        assert_eq!(second[1], Fragment::Text(interned(&lexer, " $a $1 $été")));
+        assert_eq!(tokens[2].get_type(), &TokenType::EOF);
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let string = r#""a ${b + "c"#;
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let errors = lexer.tokenize_all().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind(), &LexErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn primitive_keywords() {
+        let string = "var i: imax = 3.0 as imax; val c: char";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(tokens[3].get_type(), &TokenType::Primitive(Primitive::Imax));
+        assert_eq!(
+            tokens[12].get_type(),
+            &TokenType::Primitive(Primitive::Char)
+        );
+    }
+
+    #[test]
+    fn contextual_keywords() {
+        let string = "abstract case";
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file);
+        let tokens = lexer.tokenize_all().unwrap();
+        assert_eq!(tokens[0].get_type(), &ident(&lexer, "abstract"));
+        assert_eq!(
+            lexer.contextual(tokens[0].get_type()),
+            Some(Keyword::Object(ObjectOrientation::Abstract))
+        );
+        assert_eq!(
+            lexer.contextual(tokens[1].get_type()),
+            Some(Keyword::Control(Control::Case))
+        );
+    }
+
+    #[test]
+    fn dialects() {
+        let string = "fn func unless";
+        let dialect = Dialect::standard()
+            .without_keyword("fn")
+            .with_keyword("func", Keyword::Declare(Declare::Fn))
+            .with_contextual_keyword("unless", Keyword::Control(Control::If));
+        let file = SourceFile::new(SourceId(0), "test", string);
+        let mut lexer = Lexer::new(&file).with_dialect(dialect);
+        let tokens = lexer.tokenize_all().unwrap();
+        let types: Vec<_> = tokens.iter().map(|token| token.get_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                &ident(&lexer, "fn"),
+                &TokenType::Declare(Declare::Fn),
+                &ident(&lexer, "unless"),
+                &TokenType::EOF,
+            ]
+        );
+    }
 }
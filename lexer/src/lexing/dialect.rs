@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use crate::tokenization::{
+    Compound, Control, Declare, Literal, Loop, ObjectOrientation, Primitive, Security, TokenType,
+};
+
+/// A word with a meaning in the language
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Keyword {
+    Boolean(bool),
+    Control(Control),
+    Loop(Loop),
+    Security(Security),
+    Primitive(Primitive),
+    Compound(Compound),
+    Declare(Declare),
+    Object(ObjectOrientation),
+}
+
+impl Keyword {
+    pub fn token_type(self) -> TokenType {
+        match self {
+            Keyword::Boolean(b) => TokenType::Literal(Literal::Boolean(b)),
+            Keyword::Control(c) => TokenType::Control(c),
+            Keyword::Loop(l) => TokenType::Loop(l),
+            Keyword::Security(s) => TokenType::Security(s),
+            Keyword::Primitive(p) => TokenType::Primitive(p),
+            Keyword::Compound(c) => TokenType::Compound(c),
+            Keyword::Declare(d) => TokenType::Declare(d),
+            Keyword::Object(o) => TokenType::Object(o),
+        }
+    }
+}
+
+/// The keywords of a version of the language.
+///
+/// A reserved keyword is always lexed as its keyword token. A contextual keyword is lexed as an
+/// identifier, so it can still name things, and the parser can check with
+/// [`Lexer::contextual`](crate::lexing::Lexer::contextual) whether an identifier is one where the
+/// grammar expects it.
+#[derive(Debug, Clone)]
+pub struct Dialect {
+    reserved: HashMap<String, Keyword>,
+    contextual: HashMap<String, Keyword>,
+}
+
+impl Dialect {
+    /// A dialect without any keywords
+    pub fn empty() -> Self {
+        Dialect {
+            reserved: HashMap::new(),
+            contextual: HashMap::new(),
+        }
+    }
+
+    /// The keywords of the language, where `abstract` and `case` are contextual
+    pub fn standard() -> Self {
+        let reserved = vec![
+            ("true", Keyword::Boolean(true)),
+            ("false", Keyword::Boolean(false)),
+            ("if", Keyword::Control(Control::If)),
+            ("else", Keyword::Control(Control::Else)),
+            ("break", Keyword::Control(Control::Break)),
+            ("return", Keyword::Control(Control::Return)),
+            ("try", Keyword::Control(Control::Try)),
+            ("catch", Keyword::Control(Control::Catch)),
+            ("while", Keyword::Loop(Loop::While)),
+            ("for", Keyword::Loop(Loop::For)),
+            ("do", Keyword::Loop(Loop::Do)),
+            ("private", Keyword::Security(Security::Private)),
+            ("internal", Keyword::Security(Security::Internal)),
+            ("public", Keyword::Security(Security::Public)),
+            ("struct", Keyword::Compound(Compound::Struct)),
+            ("call", Keyword::Compound(Compound::Call)),
+            ("trait", Keyword::Compound(Compound::Trait)),
+            ("enum", Keyword::Compound(Compound::Enum)),
+            ("fn", Keyword::Declare(Declare::Fn)),
+            ("val", Keyword::Declare(Declare::Val)),
+            ("var", Keyword::Declare(Declare::Var)),
+            ("this", Keyword::Object(ObjectOrientation::This)),
+            ("super", Keyword::Object(ObjectOrientation::Super)),
+            ("is", Keyword::Object(ObjectOrientation::Is)),
+            ("as", Keyword::Object(ObjectOrientation::As)),
+        ];
+        let primitives = Primitive::ALL
+            .iter()
+            .map(|&primitive| (primitive.name(), Keyword::Primitive(primitive)));
+
+        reserved
+            .into_iter()
+            .chain(primitives)
+            .fold(Dialect::empty(), |dialect, (word, keyword)| {
+                dialect.with_keyword(word, keyword)
+            })
+            .with_contextual_keyword("abstract", Keyword::Object(ObjectOrientation::Abstract))
+            .with_contextual_keyword("case", Keyword::Control(Control::Case))
+    }
+
+    /// Reserves a word as a keyword
+    pub fn with_keyword<S: Into<String>>(mut self, word: S, keyword: Keyword) -> Self {
+        let word = word.into();
+        self.contextual.remove(&word);
+        self.reserved.insert(word, keyword);
+        self
+    }
+
+    /// Makes a word a contextual keyword
+    pub fn with_contextual_keyword<S: Into<String>>(mut self, word: S, keyword: Keyword) -> Self {
+        let word = word.into();
+        self.reserved.remove(&word);
+        self.contextual.insert(word, keyword);
+        self
+    }
+
+    /// Makes a word an ordinary identifier
+    pub fn without_keyword(mut self, word: &str) -> Self {
+        self.reserved.remove(word);
+        self.contextual.remove(word);
+        self
+    }
+
+    /// Gets the keyword a word is reserved as
+    pub fn reserved(&self, word: &str) -> Option<Keyword> {
+        self.reserved.get(word).copied()
+    }
+
+    /// Gets the keyword a word can be used as, if it is a contextual keyword
+    pub fn contextual(&self, word: &str) -> Option<Keyword> {
+        self.contextual.get(word).copied()
+    }
+
+    pub(crate) fn reserved_words(&self) -> impl Iterator<Item = (&str, Keyword)> {
+        self.reserved
+            .iter()
+            .map(|(word, &keyword)| (word.as_str(), keyword))
+    }
+
+    pub(crate) fn contextual_words(&self) -> impl Iterator<Item = (&str, Keyword)> {
+        self.contextual
+            .iter()
+            .map(|(word, &keyword)| (word.as_str(), keyword))
+    }
+}
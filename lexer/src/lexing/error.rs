@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// What went wrong while lexing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexErrorKind {
+    /// A character that can't start any token
+    UnsupportedCharacter(char),
+    /// A number literal without digits, or with a digit its base doesn't allow
+    InvalidNumber,
+    /// A number literal suffix that isn't a numeric primitive, or a float type on an integer
+    InvalidSuffix(String),
+    /// A number literal too large for its type
+    NumberOutOfRange,
+    /// A char literal that isn't closed by a `'` after its character
+    UnterminatedChar,
+    /// `''`, which has no character
+    EmptyChar,
+    /// An escape code that isn't supported
+    UnknownEscape(char),
+    /// A `\x` escape that isn't two hex digits up to 7F, or a `\u` escape that isn't up to six
+    /// hex digits in braces naming a char
+    InvalidEscape,
+    /// A string literal that reaches the end of the file before its closing `"`
+    UnterminatedString,
+    /// A block comment that isn't closed before the end of the file
+    UnterminatedComment,
+    /// `..`, which is neither `.` nor `...`
+    IncompleteEllipsis,
+    /// A source file of 4 GiB or more, which is too large for the 32 bit positions of tokens
+    FileTooLarge,
+}
+
+impl Display for LexErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LexErrorKind::UnsupportedCharacter(c) => write!(f, "unsupported character {:?}", c),
+            LexErrorKind::InvalidNumber => write!(f, "invalid number literal"),
+            LexErrorKind::InvalidSuffix(suffix) => {
+                write!(f, "invalid suffix {} for number literal", suffix)
+            }
+            LexErrorKind::NumberOutOfRange => write!(f, "number literal is out of range"),
+            LexErrorKind::UnterminatedChar => write!(f, "char literal must be closed by a '"),
+            LexErrorKind::EmptyChar => write!(f, "empty char literal"),
+            LexErrorKind::UnknownEscape(c) => write!(f, "unknown escape code \\{}", c),
+            LexErrorKind::InvalidEscape => write!(f, "invalid escape code"),
+            LexErrorKind::UnterminatedString => write!(f, "string literal is never closed"),
+            LexErrorKind::UnterminatedComment => write!(f, "block comment is never closed"),
+            LexErrorKind::IncompleteEllipsis => {
+                write!(f, ".. is not a valid operator, needs to be either . or ...")
+            }
+            LexErrorKind::FileTooLarge => write!(f, "source file must be smaller than 4 GiB"),
+        }
+    }
+}
+
+/// An error found while lexing, and where it was found. Errors are rare, so the details are boxed
+/// to keep a lexing result no bigger than a token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError(Box<Details>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Details {
+    kind: LexErrorKind,
+    filename: String,
+    line_number: usize,
+    column: usize,
+    /// The byte offsets of the offending input
+    span: Range<usize>,
+}
+
+impl LexError {
+    pub fn new(
+        kind: LexErrorKind,
+        filename: String,
+        line_number: usize,
+        column: usize,
+        span: Range<usize>,
+    ) -> Self {
+        LexError(Box::new(Details {
+            kind,
+            filename,
+            line_number,
+            column,
+            span,
+        }))
+    }
+
+    pub fn kind(&self) -> &LexErrorKind {
+        &self.0.kind
+    }
+
+    pub fn get_filename(&self) -> &str {
+        &self.0.filename
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.0.line_number
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.0.column
+    }
+
+    pub fn get_span(&self) -> Range<usize> {
+        self.0.span.clone()
+    }
+
+    /// Shows the error under the line of `source` it was found on, with the offending input
+    /// underlined by carets. Only the first line of an error spanning several is shown.
+    pub fn render(&self, source: &str) -> String {
+        let start = self.0.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let end = self.0.span.end.max(start).min(line_start + line.len());
+
+        let offset = source[line_start..start].chars().count();
+        let width = source[start..end].chars().count().max(1);
+        let gutter = " ".repeat(self.0.line_number.to_string().len());
+        format!(
+            "error: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+            self.0.kind,
+            gutter,
+            self.0.filename,
+            self.0.line_number,
+            self.0.column,
+            gutter,
+            self.0.line_number,
+            line,
+            gutter,
+            " ".repeat(offset),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.0.filename, self.0.line_number, self.0.column, self.0.kind
+        )
+    }
+}
+
+impl Error for LexError {}
+
+#[cfg(test)]
+mod test {
+    use crate::lexing::{LexError, LexErrorKind};
+
+    #[test]
+    fn render_underlines() {
+        let source = "val a = 1;\nval b = a # 2;\n";
+        let error = LexError::new(
+            LexErrorKind::UnsupportedCharacter('#'),
+            "test".to_string(),
+            2,
+            11,
+            21..22,
+        );
+        assert_eq!(
+            error.render(source),
+            "error: unsupported character '#'\n --> test:2:11\n  |\n2 | val b = a # 2;\n  |           ^\n"
+        );
+        assert_eq!(error.to_string(), "test:2:11: unsupported character '#'");
+    }
+}
@@ -0,0 +1,231 @@
+use std::ops::Range;
+
+use crate::lexing::{check_length, LexError, Lexer, Mark};
+use crate::tokenization::Token;
+
+/// How many bytes past the end of a token and its trivia the lexer may look to decide where they
+/// end, like the digit in `1.5` that makes a number a float, or the fourth `/` that makes a `////`
+/// comment plain trivia instead of a doc comment
+const LOOKAHEAD: usize = 3;
+
+/// A change to the text of a source file, which replaces a byte range of the old text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    range: Range<usize>,
+    replacement: String,
+}
+
+impl TextEdit {
+    pub fn new<S: Into<String>>(range: Range<usize>, replacement: S) -> Self {
+        TextEdit {
+            range,
+            replacement: replacement.into(),
+        }
+    }
+
+    pub fn get_range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+
+    pub fn get_replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// Makes the edit to the old text
+    pub fn apply(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len() + self.replacement.len());
+        output.push_str(&text[..self.range.start]);
+        output.push_str(&self.replacement);
+        output.push_str(&text[self.range.end..]);
+        output
+    }
+}
+
+impl Lexer<'_> {
+    /// Updates the tokens of the old text of this lexer's file after an edit, without lexing the
+    /// whole file again. The lexer must be over the edited text, and must intern into the
+    /// interner and keep the comments and trivia that the old tokens were lexed with.
+    ///
+    /// Lexing starts a little before the edit, and stops once it reaches an old token after the
+    /// edit, since the rest of the file lexes the same as before. The old tokens after that are
+    /// moved to where they are in the new text.
+    ///
+    /// Gets the indices of the tokens that were lexed again. The tokens before them are unchanged,
+    /// and the tokens after them were only moved. If the new text doesn't lex, the old tokens are
+    /// left as they were.
+    pub fn relex(
+        &mut self,
+        tokens: &mut Vec<Token>,
+        edit: &TextEdit,
+    ) -> Result<Range<usize>, Vec<LexError>> {
+        check_length(self.text.len(), self.filename).map_err(|error| vec![error])?;
+        let edit_start = edit.range.start;
+        let offset = edit.replacement.len() as isize - edit.range.len() as isize;
+
+        // A token is kept if neither it, its trivia, nor what the lexer looked at past it was
+        // touched by the edit
+        let kept =
+            tokens.partition_point(|token| token.get_full_span().end + LOOKAHEAD < edit_start);
+        let (start, restart) = match kept.checked_sub(1).map(|index| &tokens[index]) {
+            None => (
+                Mark {
+                    line_number: 1,
+                    column: 1,
+                    position: 0,
+                },
+                0,
+            ),
+            Some(previous) => (
+                Mark {
+                    line_number: previous.get_line_number(),
+                    column: previous.get_column(),
+                    position: previous.get_span().start,
+                },
+                previous.get_full_span().end,
+            ),
+        };
+        self.reset(start);
+        while self.position < restart {
+            self.next_char();
+        }
+        self.lookahead.clear();
+        self.finished = false;
+
+        // The old tokens that start after the edit, which lexing can stop at
+        let mut next_old =
+            tokens.partition_point(|token| token.get_full_span().start < edit.range.end);
+        let mut relexed = vec![];
+        let mut errors = vec![];
+        let mut synced = None;
+        while !self.finished {
+            match self.single_lex() {
+                Ok(token) => {
+                    let token_start = token.get_full_span().start as isize;
+                    while next_old < tokens.len()
+                        && (tokens[next_old].get_full_span().start as isize + offset) < token_start
+                    {
+                        next_old += 1;
+                    }
+                    match tokens.get(next_old) {
+                        Some(old) if old.get_full_span().start as isize + offset == token_start => {
+                            synced = Some((next_old, token));
+                            break;
+                        }
+                        _ => relexed.push(token),
+                    }
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let changed = kept..kept + relexed.len();
+        let mut rest = match synced {
+            Some((index, token)) => {
+                let old = &tokens[index];
+                let lines = token.get_line_number() as isize - old.get_line_number() as isize;
+                let columns = token.get_column() as isize - old.get_column() as isize;
+                let line = old.get_line_number();
+                let mut rest = tokens.split_off(index);
+                for token in &mut rest {
+                    token.shift(offset, lines, line, columns);
+                }
+                rest
+            }
+            None => vec![],
+        };
+        tokens.truncate(kept);
+        tokens.append(&mut relexed);
+        tokens.append(&mut rest);
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lexing::{Lexer, TextEdit};
+    use crate::source::{SourceFile, SourceId};
+
+    /// Relexes the text after an edit, checks that it matches lexing the new text from scratch,
+    /// and returns the range of tokens that changed
+    fn relex_matches(text: &str, edit: TextEdit, trivia: bool) -> std::ops::Range<usize> {
+        let old_file = SourceFile::new(SourceId(0), "test", text);
+        let new_file = SourceFile::new(SourceId(0), "test", edit.apply(text));
+        fn configure(lexer: Lexer, trivia: bool) -> Lexer {
+            if trivia {
+                lexer.with_trivia()
+            } else {
+                lexer
+            }
+        }
+
+        let mut lexer = configure(Lexer::new(&old_file), trivia);
+        let mut tokens = lexer.tokenize_all().unwrap();
+        let mut lexer =
+            configure(Lexer::new(&new_file), trivia).with_interner(lexer.into_interner());
+        let changed = lexer.relex(&mut tokens, &edit).unwrap();
+        let mut lexer =
+            configure(Lexer::new(&new_file), trivia).with_interner(lexer.into_interner());
+        assert_eq!(tokens, lexer.tokenize_all().unwrap());
+        changed
+    }
+
+    #[test]
+    fn relexes_around_edit() {
+        let text = "val total = 1;\nfn f() {\n    return total + 2;\n}\n";
+        let typed = TextEdit::new(36..36, "s");
+        for &trivia in &[false, true] {
+            assert_eq!(relex_matches(text, typed.clone(), trivia), 10..12);
+        }
+    }
+
+    #[test]
+    fn moves_lines_and_columns() {
+        let text = "val a = 1; val s = \"x ${a} $a\";\nval b = a;\n";
+        let edits = vec![
+            TextEdit::new(8..9, "100"),
+            TextEdit::new(4..5, "longer\n  name"),
+            TextEdit::new(10..32, ""),
+            TextEdit::new(0..0, "// start\n"),
+            TextEdit::new(43..43, "end"),
+        ];
+        for edit in edits {
+            for &trivia in &[false, true] {
+                relex_matches(text, edit.clone(), trivia);
+            }
+        }
+    }
+
+    #[test]
+    fn relexes_what_the_edit_joins() {
+        let cases = vec![
+            ("x 1. y", TextEdit::new(4..4, "5")),
+            ("a / b", TextEdit::new(3..3, "/")),
+            ("x a\" y \"b", TextEdit::new(2..4, "\"a")),
+            ("one two", TextEdit::new(3..4, "")),
+            ("f(a) /* c */ g", TextEdit::new(8..9, "\n")),
+        ];
+        for (text, edit) in cases {
+            for &trivia in &[false, true] {
+                relex_matches(text, edit.clone(), trivia);
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_tokens_after_errors() {
+        let text = "val a = 1;\nval b = a;\n";
+        let edit = TextEdit::new(8..8, "\"");
+        let old_file = SourceFile::new(SourceId(0), "test", text);
+        let new_file = SourceFile::new(SourceId(0), "test", edit.apply(text));
+        let mut lexer = Lexer::new(&old_file);
+        let mut tokens = lexer.tokenize_all().unwrap();
+        let mut lexer = Lexer::new(&old_file).with_interner(lexer.into_interner());
+        let old = lexer.tokenize_all().unwrap();
+        let mut lexer = Lexer::new(&new_file).with_interner(lexer.into_interner());
+        assert!(lexer.relex(&mut tokens, &edit).is_err());
+        assert_eq!(tokens, old);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod tokenization;
 
+pub mod interner;
 pub mod lexing;
+pub mod source;
@@ -0,0 +1,71 @@
+/// Identifies a [`SourceFile`], so that tokens can refer to their file without copying its name
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SourceId(pub u32);
+
+/// A file of source code
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    id: SourceId,
+    name: String,
+    text: String,
+}
+
+impl SourceFile {
+    pub fn new<N: Into<String>, T: Into<String>>(id: SourceId, name: N, text: T) -> Self {
+        SourceFile {
+            id,
+            name: name.into(),
+            text: text.into(),
+        }
+    }
+
+    pub fn get_id(&self) -> SourceId {
+        self.id
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Every source file of a program, which gives each one its id
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add<N: Into<String>, T: Into<String>>(&mut self, name: N, text: T) -> SourceId {
+        let id = SourceId(self.files.len() as u32);
+        self.files.push(SourceFile::new(id, name, text));
+        id
+    }
+
+    pub fn get(&self, id: SourceId) -> Option<&SourceFile> {
+        self.files.get(id.0 as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::source::{SourceId, SourceMap};
+
+    #[test]
+    fn source_map_gives_ids() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.ml", "fn main() {}");
+        let lib = map.add("lib.ml", "val x = 1;");
+        assert_ne!(main, lib);
+        assert_eq!(map.get(lib).unwrap().get_name(), "lib.ml");
+        assert_eq!(map.get(main).unwrap().get_text(), "fn main() {}");
+        assert!(map.get(SourceId(2)).is_none());
+    }
+}
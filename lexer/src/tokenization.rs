@@ -1,10 +1,15 @@
+use std::ops::Range;
+
+use crate::interner::Symbol;
+use crate::source::SourceId;
+
 mod subtypes;
 
 pub use subtypes::*;
 
 #[derive(Debug, PartialEq)]
 pub enum TokenType {
-    Identifier(String),
+    Identifier(Symbol),
     Literal(Literal),
     Control(Control),
     Loop(Loop),
@@ -16,29 +21,114 @@ pub enum TokenType {
     Structural(Structural),
     Operator(Operator),
     CompoundAssignment(Operator),
+    /// The interned text of a comment, which is only lexed when the lexer keeps comments
+    Comment(Symbol),
+    Doc(Doc),
     EOF,
 }
 
+/// A token of a source file. Its positions are stored in 32 bits to keep tokens small, so the
+/// lexer rejects source files of 4 GiB or more.
+#[derive(Debug, PartialEq)]
 pub struct Token {
     token_type: TokenType,
-    filename: String,
-    line_number: usize,
-    column: usize,
+    source: SourceId,
+    /// The byte offsets of the token in its source file
+    start: u32,
+    end: u32,
+    /// Where the trivia before the token starts, and where the trivia after it ends
+    leading: u32,
+    trailing: u32,
+    line_number: u32,
+    column: u32,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, filename: String, line_number: usize, column: usize) -> Self {
+    pub fn new(
+        token_type: TokenType,
+        source: SourceId,
+        span: Range<usize>,
+        line_number: usize,
+        column: usize,
+    ) -> Self {
         Self {
             token_type,
-            filename,
-            line_number,
-            column,
+            source,
+            start: span.start as u32,
+            end: span.end as u32,
+            leading: span.start as u32,
+            trailing: span.end as u32,
+            line_number: line_number as u32,
+            column: column as u32,
         }
     }
 
+    /// Gives the token the whitespace and comments around it, from the start of its leading
+    /// trivia to the end of its trailing trivia
+    pub fn with_trivia(mut self, leading: usize, trailing: usize) -> Self {
+        self.leading = leading as u32;
+        self.trailing = trailing as u32;
+        self
+    }
+
     pub fn get_type(&self) -> &TokenType {
         &self.token_type
     }
+
+    pub fn get_source(&self) -> SourceId {
+        self.source
+    }
+
+    pub fn get_span(&self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+
+    /// The byte offsets of the whitespace and comments before the token
+    pub fn get_leading_trivia(&self) -> Range<usize> {
+        self.leading as usize..self.start as usize
+    }
+
+    /// The byte offsets of the whitespace and comments after the token on its line
+    pub fn get_trailing_trivia(&self) -> Range<usize> {
+        self.end as usize..self.trailing as usize
+    }
+
+    /// The byte offsets of the token with its trivia
+    pub fn get_full_span(&self) -> Range<usize> {
+        self.leading as usize..self.trailing as usize
+    }
+
+    /// Moves the token, and the tokens of an interpolated string, after an edit before them that
+    /// moved the text by `offset` bytes and `lines` lines. Tokens on line `line` were on the same
+    /// line as the edit, so they also move by `columns` columns.
+    pub(crate) fn shift(&mut self, offset: isize, lines: isize, line: usize, columns: isize) {
+        let moved = |value: u32, by: isize| (value as isize + by) as u32;
+        if self.line_number as usize == line {
+            self.column = moved(self.column, columns);
+        }
+        self.line_number = moved(self.line_number, lines);
+        self.start = moved(self.start, offset);
+        self.end = moved(self.end, offset);
+        self.leading = moved(self.leading, offset);
+        self.trailing = moved(self.trailing, offset);
+        if let TokenType::Literal(Literal::Interpolated(fragments)) = &mut self.token_type {
+            for fragment in fragments.iter_mut() {
+                if let Fragment::Expression(tokens) = fragment {
+                    for token in tokens {
+                        token.shift(offset, lines, line, columns);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_line_number(&self) -> usize {
+        self.line_number as usize
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column as usize
+    }
 }
 
 pub trait HasTokenType {
@@ -1,13 +1,29 @@
+use crate::interner::Symbol;
+use crate::tokenization::Token;
+
 #[derive(Debug, PartialEq)]
 pub enum Literal {
     Character(char),
     Boolean(bool),
-    Integer(usize),
-    Float(f64),
-    String(String),
+    /// An integer, which may have a suffix giving its type
+    Integer(u64, Option<Primitive>),
+    /// A float, which may have a suffix giving its type
+    Float(f64, Option<Primitive>),
+    /// A string, with its escape codes decoded and interned
+    String(Symbol),
+    /// A string with expressions interpolated into it
+    Interpolated(Box<[Fragment]>),
 }
 
+/// A part of an interpolated string
 #[derive(Debug, PartialEq)]
+pub enum Fragment {
+    Text(Symbol),
+    /// The tokens of a `$name` or `${expression}`
+    Expression(Vec<Token>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Control {
     If,
     Else,
@@ -18,21 +34,21 @@ pub enum Control {
     Case,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Loop {
     While,
     For,
     Do,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Security {
     Private,
     Public,
     Internal,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Primitive {
     Char,
     I8,
@@ -44,7 +60,41 @@ pub enum Primitive {
     F32,
 }
 
-#[derive(Debug, PartialEq)]
+impl Primitive {
+    pub const ALL: [Primitive; 8] = [
+        Primitive::Char,
+        Primitive::I8,
+        Primitive::I16,
+        Primitive::I32,
+        Primitive::I64,
+        Primitive::Imax,
+        Primitive::F64,
+        Primitive::F32,
+    ];
+
+    /// The name of the type in source code
+    pub fn name(&self) -> &'static str {
+        match self {
+            Primitive::Char => "char",
+            Primitive::I8 => "i8",
+            Primitive::I16 => "i16",
+            Primitive::I32 => "i32",
+            Primitive::I64 => "i64",
+            Primitive::Imax => "imax",
+            Primitive::F64 => "f64",
+            Primitive::F32 => "f32",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Primitive::ALL
+            .iter()
+            .copied()
+            .find(|primitive| primitive.name() == name)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Compound {
     Struct,
     Call,
@@ -52,14 +102,14 @@ pub enum Compound {
     Enum,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Declare {
     Fn,
     Val,
     Var,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ObjectOrientation {
     This,
     Super,
@@ -68,6 +118,15 @@ pub enum ObjectOrientation {
     As,
 }
 
+/// The interned text of a doc comment, without its `///` or `//!`
+#[derive(Debug, PartialEq)]
+pub enum Doc {
+    /// ///, which documents the declaration after it
+    Outer(Symbol),
+    /// //!, which documents the declaration it is in
+    Inner(Symbol),
+}
+
 /// Used for structure
 #[derive(Debug, PartialEq)]
 pub enum Structural {
@@ -0,0 +1,597 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::instruction_set::Instruction;
+
+pub type BlockId = usize;
+
+/// A straight run of instructions that is only entered at its first instruction and only left
+/// after its last one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub instructions: Range<usize>,
+}
+
+impl BasicBlock {
+    /// The address of the instruction that ends the block
+    pub fn last(&self) -> usize {
+        self.instructions.end - 1
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Execution continues onto the next block, including returning to just after a `Call`
+    Fallthrough,
+    /// An unconditional `Jump`
+    Jump,
+    /// The taken side of a `ConditionalJump`
+    Branch,
+    /// From a `Call` to the start of the called procedure
+    Call,
+    /// From a `TailCall` to the start of the procedure that replaces the current one
+    TailCall,
+    /// From a `Spawn` to the start of the new execution context
+    Spawn,
+    /// From a `Ret` to the instruction after every `Call` into its procedure
+    Return,
+}
+
+impl EdgeKind {
+    /// Whether the edge stays within one procedure
+    pub fn is_intraprocedural(&self) -> bool {
+        match self {
+            EdgeKind::Fallthrough | EdgeKind::Jump | EdgeKind::Branch => true,
+            EdgeKind::Call | EdgeKind::TailCall | EdgeKind::Spawn | EdgeKind::Return => false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: BlockId,
+    pub to: BlockId,
+    pub kind: EdgeKind,
+}
+
+/// The control flow graph of a flat instruction stream
+pub struct ControlFlowGraph<'a> {
+    instructions: &'a [Instruction],
+    blocks: Vec<BasicBlock>,
+    block_of: Vec<BlockId>,
+    edges: Vec<Edge>,
+    roots: Vec<BlockId>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    /// Builds the graph for `instructions`, where every address in `entry_points` can be entered
+    /// from outside of the instructions. Every entry point, and every `Call`, `TailCall` or `Spawn`
+    /// target starts a procedure.
+    pub fn new(instructions: &'a [Instruction], entry_points: &[usize]) -> Self {
+        let length = instructions.len();
+        let mut leaders = BTreeSet::new();
+        if length > 0 {
+            leaders.insert(0);
+        }
+        for entry in entry_points {
+            if *entry < length {
+                leaders.insert(*entry);
+            }
+        }
+        for (index, instruction) in instructions.iter().enumerate() {
+            if let Some(target) = instruction.jump_target() {
+                if target < length {
+                    leaders.insert(target);
+                }
+            }
+            let ends_block = instruction.jump_target().is_some()
+                || instruction.is_call()
+                || !instruction.falls_through();
+            if ends_block && index + 1 < length {
+                leaders.insert(index + 1);
+            }
+        }
+
+        let leaders: Vec<usize> = leaders.into_iter().collect();
+        let mut blocks = Vec::with_capacity(leaders.len());
+        let mut block_of = vec![0; length];
+        for (id, start) in leaders.iter().enumerate() {
+            let end = leaders.get(id + 1).copied().unwrap_or(length);
+            for owner in &mut block_of[*start..end] {
+                *owner = id;
+            }
+            blocks.push(BasicBlock {
+                instructions: *start..end,
+            });
+        }
+
+        let mut graph = ControlFlowGraph {
+            instructions,
+            blocks,
+            block_of,
+            edges: vec![],
+            roots: vec![],
+        };
+        graph.add_local_edges();
+
+        let mut roots: Vec<BlockId> = entry_points
+            .iter()
+            .filter(|entry| **entry < length)
+            .map(|entry| graph.block_of[*entry])
+            .collect();
+        for edge in &graph.edges {
+            let starts_procedure = !edge.kind.is_intraprocedural() && edge.kind != EdgeKind::Return;
+            if starts_procedure && !roots.contains(&edge.to) {
+                roots.push(edge.to);
+            }
+        }
+        graph.roots = roots;
+        graph.add_return_edges();
+        graph
+    }
+
+    fn add_local_edges(&mut self) {
+        for (id, block) in self.blocks.iter().enumerate() {
+            let last = &self.instructions[block.last()];
+            let next = if id + 1 < self.blocks.len() {
+                Some(id + 1)
+            } else {
+                None
+            };
+            let target = last
+                .jump_target()
+                .and_then(|target| self.block_of.get(target).copied());
+
+            match (last, target) {
+                (Instruction::Jump(_), Some(target)) => self.edges.push(Edge {
+                    from: id,
+                    to: target,
+                    kind: EdgeKind::Jump,
+                }),
+                (Instruction::ConditionalJump(..), Some(target)) => self.edges.push(Edge {
+                    from: id,
+                    to: target,
+                    kind: EdgeKind::Branch,
+                }),
+                (Instruction::Call(_), Some(target)) => self.edges.push(Edge {
+                    from: id,
+                    to: target,
+                    kind: EdgeKind::Call,
+                }),
+                (Instruction::TailCall(_), Some(target)) => self.edges.push(Edge {
+                    from: id,
+                    to: target,
+                    kind: EdgeKind::TailCall,
+                }),
+                (Instruction::Spawn(_), Some(target)) => self.edges.push(Edge {
+                    from: id,
+                    to: target,
+                    kind: EdgeKind::Spawn,
+                }),
+                _ => {}
+            }
+
+            if let (true, Some(next)) = (last.falls_through(), next) {
+                self.edges.push(Edge {
+                    from: id,
+                    to: next,
+                    kind: EdgeKind::Fallthrough,
+                });
+            }
+        }
+    }
+
+    fn add_return_edges(&mut self) {
+        let procedures: Vec<(BlockId, Vec<BlockId>)> = self
+            .roots
+            .iter()
+            .map(|root| (*root, self.procedure(*root)))
+            .collect();
+
+        let mut return_sites: HashMap<BlockId, BTreeSet<BlockId>> = HashMap::new();
+        for edge in &self.edges {
+            if edge.kind == EdgeKind::Call && edge.from + 1 < self.blocks.len() {
+                return_sites
+                    .entry(edge.to)
+                    .or_default()
+                    .insert(edge.from + 1);
+            }
+        }
+
+        // A procedure entered by a tail call returns to wherever the procedure that made the
+        // tail call would have returned to
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (root, blocks) in &procedures {
+                let sites = return_sites.get(root).cloned().unwrap_or_default();
+                for edge in &self.edges {
+                    if edge.kind == EdgeKind::TailCall && blocks.contains(&edge.from) {
+                        let target_sites = return_sites.entry(edge.to).or_default();
+                        for site in &sites {
+                            changed |= target_sites.insert(*site);
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut return_edges = vec![];
+        for (root, blocks) in &procedures {
+            let sites = match return_sites.get(root) {
+                Some(sites) => sites,
+                None => continue,
+            };
+            for block in blocks {
+                if let Instruction::Ret(_) = self.instructions[self.blocks[*block].last()] {
+                    for site in sites {
+                        return_edges.push(Edge {
+                            from: *block,
+                            to: *site,
+                            kind: EdgeKind::Return,
+                        });
+                    }
+                }
+            }
+        }
+        self.edges.append(&mut return_edges);
+    }
+
+    pub fn blocks(&self) -> &Vec<BasicBlock> {
+        &self.blocks
+    }
+
+    pub fn edges(&self) -> &Vec<Edge> {
+        &self.edges
+    }
+
+    /// The blocks that start a procedure, being every entry point and every `Call`, `TailCall` or
+    /// `Spawn` target
+    pub fn roots(&self) -> &Vec<BlockId> {
+        &self.roots
+    }
+
+    /// Gets the block that contains the instruction at `address`
+    pub fn block_of(&self, address: usize) -> Option<BlockId> {
+        self.block_of.get(address).copied()
+    }
+
+    pub fn successors(&self, block: BlockId) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.from == block)
+    }
+
+    pub fn predecessors(&self, block: BlockId) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.to == block)
+    }
+
+    fn local_successors(&self, block: BlockId) -> impl Iterator<Item = BlockId> + '_ {
+        self.successors(block)
+            .filter(|edge| edge.kind.is_intraprocedural())
+            .map(|edge| edge.to)
+    }
+
+    /// Every block reachable from `root` without following a `Call` or `Ret`, in reverse
+    /// postorder
+    pub fn procedure(&self, root: BlockId) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = vec![];
+        let mut stack = vec![(root, false)];
+        while let Some((block, finished)) = stack.pop() {
+            if finished {
+                postorder.push(block);
+                continue;
+            }
+            if visited[block] {
+                continue;
+            }
+            visited[block] = true;
+            stack.push((block, true));
+            for next in self.local_successors(block) {
+                if !visited[next] {
+                    stack.push((next, false));
+                }
+            }
+        }
+        postorder.reverse();
+        postorder
+    }
+
+    /// Computes the dominator tree of the procedure starting at `root`
+    pub fn dominators(&self, root: BlockId) -> Dominators {
+        let order = self.procedure(root);
+        let mut position = HashMap::new();
+        for (index, block) in order.iter().enumerate() {
+            position.insert(*block, index);
+        }
+
+        let mut immediate = vec![None; self.blocks.len()];
+        immediate[root] = Some(root);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter().skip(1) {
+                let mut new_dominator: Option<BlockId> = None;
+                for edge in self.predecessors(*block) {
+                    if !edge.kind.is_intraprocedural() || immediate[edge.from].is_none() {
+                        continue;
+                    }
+                    new_dominator = Some(match new_dominator {
+                        None => edge.from,
+                        Some(current) => intersect(&immediate, &position, edge.from, current),
+                    });
+                }
+                if new_dominator.is_some() && immediate[*block] != new_dominator {
+                    immediate[*block] = new_dominator;
+                    changed = true;
+                }
+            }
+        }
+
+        Dominators { root, immediate }
+    }
+
+    /// Finds the natural loops of every procedure. Loops that share a header are merged.
+    pub fn loops(&self) -> Vec<Loop> {
+        let mut loops: Vec<Loop> = vec![];
+        for root in &self.roots {
+            let dominators = self.dominators(*root);
+            for block in self.procedure(*root) {
+                for edge in self.successors(block) {
+                    if !edge.kind.is_intraprocedural() || !dominators.dominates(edge.to, block) {
+                        continue;
+                    }
+
+                    let header = edge.to;
+                    let mut body = BTreeSet::new();
+                    body.insert(header);
+                    let mut work_list = vec![block];
+                    while let Some(next) = work_list.pop() {
+                        if body.insert(next) {
+                            work_list.extend(
+                                self.predecessors(next)
+                                    .filter(|edge| edge.kind.is_intraprocedural())
+                                    .map(|edge| edge.from),
+                            );
+                        }
+                    }
+
+                    match loops.iter_mut().find(|other| other.header == header) {
+                        Some(existing) => {
+                            existing.body.extend(body);
+                            existing.latches.insert(block);
+                        }
+                        None => {
+                            let mut latches = BTreeSet::new();
+                            latches.insert(block);
+                            loops.push(Loop {
+                                header,
+                                latches,
+                                body,
+                            })
+                        }
+                    }
+                }
+            }
+        }
+        loops
+    }
+
+    /// Exports the graph in the Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let mut output = String::new();
+        writeln!(output, "digraph cfg {{").unwrap();
+        writeln!(output, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        for (id, block) in self.blocks.iter().enumerate() {
+            let mut label = String::new();
+            for address in block.instructions.clone() {
+                let line = format!("{}: {:?}", address, self.instructions[address]);
+                label.push_str(&escape_dot(&line));
+                label.push_str("\\l");
+            }
+            let style = if self.roots.contains(&id) {
+                ", style=bold"
+            } else {
+                ""
+            };
+            writeln!(output, "    block{} [label=\"{}\"{}];", id, label, style).unwrap();
+        }
+        for edge in &self.edges {
+            let attributes = match edge.kind {
+                EdgeKind::Fallthrough => "",
+                EdgeKind::Jump => " [label=\"jump\"]",
+                EdgeKind::Branch => " [label=\"branch\", color=blue]",
+                EdgeKind::Call => " [label=\"call\", style=dashed]",
+                EdgeKind::TailCall => " [label=\"tail call\", style=dashed]",
+                EdgeKind::Spawn => " [label=\"spawn\", style=dashed]",
+                EdgeKind::Return => " [label=\"return\", style=dotted]",
+            };
+            writeln!(
+                output,
+                "    block{} -> block{}{};",
+                edge.from, edge.to, attributes
+            )
+            .unwrap();
+        }
+        writeln!(output, "}}").unwrap();
+        output
+    }
+}
+
+fn intersect(
+    immediate: &[Option<BlockId>],
+    position: &HashMap<BlockId, usize>,
+    mut first: BlockId,
+    mut second: BlockId,
+) -> BlockId {
+    while first != second {
+        while position[&first] > position[&second] {
+            first = immediate[first].unwrap();
+        }
+        while position[&second] > position[&first] {
+            second = immediate[second].unwrap();
+        }
+    }
+    first
+}
+
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The dominator tree of one procedure
+pub struct Dominators {
+    root: BlockId,
+    immediate: Vec<Option<BlockId>>,
+}
+
+impl Dominators {
+    pub fn root(&self) -> BlockId {
+        self.root
+    }
+
+    /// Gets the closest block that every path from the root to `block` must pass through.
+    /// Returns `None` for the root, and for blocks outside of the procedure.
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        if block == self.root {
+            return None;
+        }
+        self.immediate.get(block).copied().flatten()
+    }
+
+    /// Whether every path from the root to `block` passes through `dominator`
+    pub fn dominates(&self, dominator: BlockId, mut block: BlockId) -> bool {
+        if self.immediate.get(block).copied().flatten().is_none() {
+            return false;
+        }
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.immediate_dominator(block) {
+                Some(next) => block = next,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// A natural loop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// The only block of the loop that can be entered from outside of it
+    pub header: BlockId,
+    /// The blocks that jump back to the header
+    pub latches: BTreeSet<BlockId>,
+    pub body: BTreeSet<BlockId>,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::cfg::{ControlFlowGraph, Edge, EdgeKind};
+    use crate::instruction_set::Immediate::{USize, U32};
+    use crate::instruction_set::Instruction::*;
+    use crate::instruction_set::{ArithmeticMode, Instruction, JumpType, Operation};
+
+    fn counting_loop() -> Vec<Instruction> {
+        vec![
+            PushVal(USize(10)),
+            PushVal(USize(1)),
+            Push {
+                src: crate::instruction_set::Literal::Peak,
+            },
+            PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+            ConditionalJump(JumpType::NotZero, 1),
+            PushVal(U32(0)),
+            Halt,
+        ]
+    }
+
+    #[test]
+    fn splits_blocks() {
+        let instructions = counting_loop();
+        let graph = ControlFlowGraph::new(&instructions, &[0]);
+        let ranges: Vec<_> = graph
+            .blocks()
+            .iter()
+            .map(|block| block.instructions.clone())
+            .collect();
+        assert_eq!(ranges, vec![0..1, 1..5, 5..7]);
+        assert!(graph.edges().contains(&Edge {
+            from: 1,
+            to: 1,
+            kind: EdgeKind::Branch
+        }));
+        assert!(graph.edges().contains(&Edge {
+            from: 1,
+            to: 2,
+            kind: EdgeKind::Fallthrough
+        }));
+    }
+
+    #[test]
+    fn finds_loops() {
+        let instructions = counting_loop();
+        let graph = ControlFlowGraph::new(&instructions, &[0]);
+        let dominators = graph.dominators(0);
+        assert_eq!(dominators.immediate_dominator(1), Some(0));
+        assert_eq!(dominators.immediate_dominator(2), Some(1));
+        assert!(dominators.dominates(0, 2));
+        assert!(!dominators.dominates(2, 1));
+
+        let loops = graph.loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].header, 1);
+        assert_eq!(loops[0].body.iter().copied().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn connects_calls_and_returns() {
+        let instructions = vec![Call(3), PushVal(U32(0)), Halt, Nop, Ret(None)];
+        let graph = ControlFlowGraph::new(&instructions, &[0]);
+        assert_eq!(graph.roots(), &vec![0, 2]);
+        assert!(graph.edges().contains(&Edge {
+            from: 0,
+            to: 2,
+            kind: EdgeKind::Call
+        }));
+        assert!(graph.edges().contains(&Edge {
+            from: 2,
+            to: 1,
+            kind: EdgeKind::Return
+        }));
+        assert!(graph.edges().contains(&Edge {
+            from: 0,
+            to: 1,
+            kind: EdgeKind::Fallthrough
+        }));
+    }
+
+    #[test]
+    fn tail_calls_return_to_original_caller() {
+        let instructions = vec![Call(3), PushVal(U32(0)), Halt, TailCall(4), Ret(None)];
+        let graph = ControlFlowGraph::new(&instructions, &[0]);
+        assert_eq!(graph.roots(), &vec![0, 2, 3]);
+        assert!(graph.edges().contains(&Edge {
+            from: 2,
+            to: 3,
+            kind: EdgeKind::TailCall
+        }));
+        assert!(graph.edges().contains(&Edge {
+            from: 3,
+            to: 1,
+            kind: EdgeKind::Return
+        }));
+    }
+
+    #[test]
+    fn exports_dot() {
+        let instructions = vec![Call(3), PushVal(U32(0)), Halt, Nop, Ret(None)];
+        let dot = ControlFlowGraph::new(&instructions, &[0]).to_dot();
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("block0 -> block2 [label=\"call\", style=dashed];"));
+        assert!(dot.contains("4: Ret(None)\\l"));
+    }
+}
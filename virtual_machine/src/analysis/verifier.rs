@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use crate::analysis::cfg::{BlockId, ControlFlowGraph, EdgeKind};
+use crate::instruction_set::Instruction;
+
+/// A problem found by the [`Verifier`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyError {
+    /// A jump or call targets an address outside of the instructions
+    InvalidJumpTarget { address: usize, target: usize },
+    /// An instruction can be reached with different numbers of local scopes entered
+    InconsistentScope { address: usize },
+    /// An `Exit` leaves a local scope that was never entered
+    ScopeUnderflow { address: usize },
+    /// A procedure returns without exiting every local scope it entered
+    UnbalancedScope { address: usize },
+    /// A tail call made by code that was never called, so there is nothing to return to
+    TailCallOutsideProcedure { address: usize },
+    /// A tail call made while the procedure is still in a scope it entered after its own
+    NotInTailPosition { address: usize },
+}
+
+/// Statically checks an instruction stream before it is run.
+///
+/// Every `Call` and `TailCall` target is checked as a procedure, which starts outside of any local
+/// scope. It has to enter its own scope, which it exits before a `Ret` or which a tail call exits
+/// for it. A tail call is only in tail position when the procedure is in no other scope.
+pub struct Verifier {
+    entry_points: Vec<usize>,
+    /// Addresses entered from outside of the instructions as procedures, with the number of
+    /// local scopes they start in
+    procedures: Vec<(usize, usize)>,
+}
+
+impl Verifier {
+    pub fn new(start: usize) -> Self {
+        Verifier {
+            entry_points: vec![start],
+            procedures: vec![],
+        }
+    }
+
+    pub fn with_entry_point(mut self, entry: usize) -> Self {
+        self.entry_points.push(entry);
+        self
+    }
+
+    /// Marks an address that is called from outside of the instructions, such as the start of
+    /// the body of a `Function`
+    pub fn with_procedure(mut self, address: usize) -> Self {
+        self.procedures.push((address, 0));
+        self
+    }
+
+    /// Marks the start of the body of a closure, which is entered in the scope of its captured
+    /// variables
+    pub fn with_closure(mut self, address: usize) -> Self {
+        self.procedures.push((address, 1));
+        self
+    }
+
+    pub fn verify(&self, instructions: &[Instruction]) -> Result<(), Vec<VerifyError>> {
+        let mut errors = vec![];
+        for (address, instruction) in instructions.iter().enumerate() {
+            if let Some(target) = instruction.jump_target() {
+                if target >= instructions.len() {
+                    errors.push(VerifyError::InvalidJumpTarget { address, target });
+                }
+            }
+        }
+
+        let mut starts = self.entry_points.clone();
+        starts.extend(self.procedures.iter().map(|(address, _)| *address));
+        let graph = ControlFlowGraph::new(instructions, &starts);
+
+        let mut procedures: HashMap<BlockId, usize> = HashMap::new();
+        for edge in graph.edges() {
+            if matches!(edge.kind, EdgeKind::Call | EdgeKind::TailCall) {
+                procedures.insert(edge.to, 0);
+            }
+        }
+        for (address, depth) in &self.procedures {
+            if let Some(block) = graph.block_of(*address) {
+                procedures.insert(block, *depth);
+            }
+        }
+
+        for root in graph.roots() {
+            match procedures.get(root) {
+                Some(depth) => {
+                    verify_procedure(instructions, &graph, *root, *depth, true, &mut errors)
+                }
+                None => verify_procedure(instructions, &graph, *root, 0, false, &mut errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Follows the local scope depth through every block of the procedure starting at `root`
+fn verify_procedure(
+    instructions: &[Instruction],
+    graph: &ControlFlowGraph,
+    root: BlockId,
+    start_depth: usize,
+    called: bool,
+    errors: &mut Vec<VerifyError>,
+) {
+    let mut report = |error: VerifyError| {
+        if !errors.contains(&error) {
+            errors.push(error);
+        }
+    };
+
+    let mut depths: HashMap<BlockId, usize> = HashMap::new();
+    depths.insert(root, start_depth);
+    for block in graph.procedure(root) {
+        let mut depth = match depths.get(&block) {
+            Some(depth) => *depth,
+            None => continue,
+        };
+        for address in graph.blocks()[block].instructions.clone() {
+            match &instructions[address] {
+                Instruction::Enter | Instruction::Lower => depth += 1,
+                Instruction::Exit => {
+                    if depth == 0 {
+                        report(VerifyError::ScopeUnderflow { address });
+                    } else {
+                        depth -= 1;
+                    }
+                }
+                Instruction::Ret(_) if called && depth != 0 => {
+                    report(VerifyError::UnbalancedScope { address });
+                }
+                Instruction::TailCall(_) | Instruction::TailCallFunction(_) => {
+                    if !called {
+                        report(VerifyError::TailCallOutsideProcedure { address });
+                    } else if depth != 1 {
+                        report(VerifyError::NotInTailPosition { address });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for edge in graph.successors(block) {
+            if !edge.kind.is_intraprocedural() {
+                continue;
+            }
+            match depths.get(&edge.to) {
+                None => {
+                    depths.insert(edge.to, depth);
+                }
+                Some(expected) if *expected != depth => {
+                    let address = graph.blocks()[edge.to].instructions.start;
+                    report(VerifyError::InconsistentScope { address });
+                }
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analysis::verifier::{Verifier, VerifyError};
+    use crate::instruction_set::Immediate::U32;
+    use crate::instruction_set::Instruction::*;
+    use crate::instruction_set::JumpType;
+
+    #[test]
+    fn accepts_tail_calls() {
+        let instructions = vec![
+            Enter,
+            ConditionalJump(JumpType::Zero, 3),
+            TailCall(0),
+            Exit,
+            Ret(None),
+            Call(0),
+            PushVal(U32(0)),
+            Halt,
+        ];
+        assert_eq!(Verifier::new(5).verify(&instructions), Ok(()));
+    }
+
+    #[test]
+    fn rejects_tail_calls_out_of_tail_position() {
+        let instructions = vec![Enter, Lower, TailCall(0), Call(0), PushVal(U32(0)), Halt];
+        assert_eq!(
+            Verifier::new(3).verify(&instructions),
+            Err(vec![VerifyError::NotInTailPosition { address: 2 }])
+        );
+
+        let instructions = vec![Ret(None), TailCall(0)];
+        assert_eq!(
+            Verifier::new(1).verify(&instructions),
+            Err(vec![VerifyError::TailCallOutsideProcedure { address: 1 }])
+        );
+    }
+
+    #[test]
+    fn rejects_unbalanced_scopes() {
+        let instructions = vec![
+            Enter,
+            ConditionalJump(JumpType::Zero, 3),
+            Exit,
+            Ret(None),
+            Call(0),
+            Exit,
+            Call(9),
+        ];
+        assert_eq!(
+            Verifier::new(4).verify(&instructions),
+            Err(vec![
+                VerifyError::InvalidJumpTarget {
+                    address: 6,
+                    target: 9
+                },
+                VerifyError::ScopeUnderflow { address: 5 },
+                VerifyError::InconsistentScope { address: 3 },
+                VerifyError::UnbalancedScope { address: 3 },
+            ])
+        );
+    }
+
+    #[test]
+    fn accepts_closure_bodies() {
+        let instructions = vec![TailCall(2), Halt, Enter, Exit, Ret(None)];
+        assert_eq!(
+            Verifier::new(1).with_closure(0).verify(&instructions),
+            Ok(())
+        );
+        assert_eq!(
+            Verifier::new(1).with_procedure(0).verify(&instructions),
+            Err(vec![VerifyError::NotInTailPosition { address: 0 }])
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::instruction_set::Immediate;
+use crate::registers::REGISTER_COUNT;
+use crate::resolution::types::descriptor::Variant;
+use crate::resolution::Resolvable;
+
+/// The number of arguments that are passed in registers
+pub const ARGUMENT_REGISTERS: usize = REGISTER_COUNT;
+/// The caller register the return value is placed in
+pub const RETURN_REGISTER: u8 = 0;
+
+/// How the virtual machine treats the register banks across `Call` and `Ret`.
+///
+/// Under a managed convention (anything other than `Unchecked`):
+///
+/// * The first [`ARGUMENT_REGISTERS`] arguments are passed in `Caller 0` through `Caller 7`.
+/// * Any further arguments are spilled onto the value stack before the `Call`, last argument
+///   first, so the first spilled argument sits right below the return address. The callee reads
+///   them with `Literal::Argument`, and the caller pops them once the call returns.
+/// * The return value is placed in `Caller 0` ([`RETURN_REGISTER`]). `Ret(Some(src))` moves `src`
+///   there instead of pushing it onto the stack.
+/// * The caller bank may be clobbered by the callee. The callee bank is preserved across `Call`.
+/// * The callee must leave the value stack as it found it, with the return address on top.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CallingConvention {
+    /// `Call` and `Ret` only move the return address, and nothing is checked
+    Unchecked,
+    /// The callee bank is saved on `Call`, and restored on `Ret`
+    Preserving,
+    /// The callee bank is saved on `Call`, and `Ret` faults if the callee changed it
+    Verifying,
+}
+
+impl CallingConvention {
+    pub fn is_managed(&self) -> bool {
+        !matches!(self, CallingConvention::Unchecked)
+    }
+}
+
+/// The bookkeeping for a call
+pub(crate) struct CallFrame {
+    /// The depth of the value stack when the return address was pushed
+    pub(crate) stack_base: usize,
+    /// How many local scopes were entered when the call was made
+    pub(crate) scope_depth: usize,
+    /// The callee bank when the call was made, if it was made under a managed convention
+    pub(crate) saved_callee: Option<[Immediate; REGISTER_COUNT]>,
+}
+
+/// Whether two immediates hold exactly the same value, without faulting on mismatched types
+pub(crate) fn identical(first: &Immediate, second: &Immediate) -> bool {
+    use Immediate::*;
+    match (first, second) {
+        (U8(v1), U8(v2)) => v1 == v2,
+        (U16(v1), U16(v2)) => v1 == v2,
+        (U32(v1), U32(v2)) => v1 == v2,
+        (U64(v1), U64(v2)) => v1 == v2,
+        (USize(v1), USize(v2)) => v1 == v2,
+        (Float(v1), Float(v2)) => v1.to_bits() == v2.to_bits(),
+        (Double(v1), Double(v2)) => v1.to_bits() == v2.to_bits(),
+        (Char(v1), Char(v2)) => v1 == v2,
+        (Pointer(v1), Pointer(v2)) => v1 == v2,
+        (PointerConst(v1), PointerConst(v2)) => v1 == v2,
+        (Array(v1), Array(v2)) => {
+            v1.len() == v2.len()
+                && v1.iter().zip(v2).all(|pair| match pair {
+                    (Some(e1), Some(e2)) => identical(e1, e2),
+                    (None, None) => true,
+                    _ => false,
+                })
+        }
+        (Variant(v1), Variant(v2)) => identical_variants(v1, v2),
+        (DetailedType(o1), DetailedType(o2)) => o1.identical(o2),
+        (Function(f1), Function(f2)) => f1.get_identifier() == f2.get_identifier(),
+        (Closure(c1), Closure(c2)) => {
+            c1.get_function().get_identifier() == c2.get_function().get_identifier()
+                && c1.get_environment() == c2.get_environment()
+        }
+        _ => false,
+    }
+}
+
+/// Whether two variants have the same shape and hold exactly the same values
+pub(crate) fn identical_variants(first: &Variant, second: &Variant) -> bool {
+    match (first, second) {
+        (Variant::Tuple(t1), Variant::Tuple(t2)) => {
+            t1.len() == t2.len() && t1.iter().zip(t2).all(|(e1, e2)| identical(e1, e2))
+        }
+        (
+            Variant::Structure {
+                order: o1,
+                fields: f1,
+            },
+            Variant::Structure {
+                order: o2,
+                fields: f2,
+            },
+        ) => {
+            o1 == o2
+                && f1.len() == f2.len()
+                && f1
+                    .iter()
+                    .all(|(name, v1)| matches!(f2.get(name), Some(v2) if identical(v1, v2)))
+        }
+        (Variant::Empty, Variant::Empty) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::calling_convention::identical;
+    use crate::instruction_set::Immediate::{Array, Variant, U32, U8};
+    use crate::resolution::types::descriptor::Variant::Tuple;
+
+    #[test]
+    fn identical_compares_contents() {
+        let tuple = |last| Variant(Tuple(vec![U8(1), Array(vec![Some(U32(2)), last])]));
+        assert!(identical(&tuple(None), &tuple(None)));
+        assert!(!identical(&tuple(None), &tuple(Some(U32(3)))));
+        assert!(!identical(&U8(1), &U32(1)));
+    }
+}
@@ -1,3 +1,33 @@
+use crate::instruction_set::Immediate;
+use crate::instruction_set::Immediate::*;
+
+const CARRY: u16 = 1 << 0;
+const PARITY: u16 = 1 << 2;
+const ZERO: u16 = 1 << 6;
+const SIGN: u16 = 1 << 7;
+const TRAP: u16 = 1 << 8;
+const INTERRUPT_ENABLE: u16 = 1 << 9;
+const OVERFLOW: u16 = 1 << 11;
+
+/// The status flags of a virtual machine, which are modelled on the x86 FLAGS register.
+///
+/// Every `Operation` and `ComparisonOperation` computes `val1 op val2`, where `val1` is the value
+/// that was on top of the stack, and sets the status flags from it at any width as follows:
+///
+/// - `zero` is set when the result is zero
+/// - `sign` is the most significant bit of the result, or the sign bit of a float
+/// - `parity` is set when the low byte of the result has an even number of set bits. For floats,
+///   it is set when the result is NaN, like an unordered x86 comparison
+/// - `carry` is set when the unsigned result does not fit. This is the carry out of `Add`, the
+///   borrow of `Subtract` and `Compare`, the overflow of `Multiply` and `Power`, and the last bit
+///   shifted out or rotated around by a shift or rotate
+/// - `overflow` is set when the result does not fit as two's complement, for `Add`, `Subtract`
+///   and `Compare`. `Multiply` and `Power` are unsigned, and set it along with `carry`
+///
+/// Every other operation, including the bitwise ones, clears `carry` and `overflow`. The flags
+/// always describe the result that is pushed, so a saturated result sets them by its clamped value.
+/// Comparisons other than `Compare` push a boolean, being zero for true and `u8::MAX` for false,
+/// so `zero` is set when the comparison holds.
 pub struct Flags {
     pub carry: bool,
     pub parity: bool,
@@ -8,6 +38,12 @@ pub struct Flags {
     pub overflow: bool,
 }
 
+impl Default for Flags {
+    fn default() -> Self {
+        Flags::new()
+    }
+}
+
 impl Flags {
     pub fn new() -> Self {
         Flags {
@@ -24,4 +60,52 @@ impl Flags {
     pub fn reset(&mut self) {
         *self = Flags::new();
     }
+
+    /// Sets the zero, sign and parity flags from the result of an operation
+    pub fn set_result(&mut self, result: &Immediate) {
+        self.zero = result.is_zero();
+        self.sign = result.msb();
+        self.parity = match result {
+            Float(d) => d.is_nan(),
+            Double(d) => d.is_nan(),
+            U8(d) => d.count_ones() & 1 == 0,
+            U16(d) => (*d as u8).count_ones() & 1 == 0,
+            U32(d) => (*d as u8).count_ones() & 1 == 0,
+            U64(d) => (*d as u8).count_ones() & 1 == 0,
+            USize(d) => (*d as u8).count_ones() & 1 == 0,
+            Char(d) => (*d as u8).count_ones() & 1 == 0,
+            _ => false,
+        };
+    }
+
+    /// Packs the flags into a word, using the same bit positions as the x86 FLAGS register
+    pub fn to_word(&self) -> u16 {
+        let mut word = 0;
+        for (flag, bit) in [
+            (self.carry, CARRY),
+            (self.parity, PARITY),
+            (self.zero, ZERO),
+            (self.sign, SIGN),
+            (self.trap, TRAP),
+            (self.interrupt_enable, INTERRUPT_ENABLE),
+            (self.overflow, OVERFLOW),
+        ]
+        .iter()
+        {
+            if *flag {
+                word |= bit;
+            }
+        }
+        word
+    }
+
+    pub fn set_word(&mut self, word: u16) {
+        self.carry = word & CARRY != 0;
+        self.parity = word & PARITY != 0;
+        self.zero = word & ZERO != 0;
+        self.sign = word & SIGN != 0;
+        self.trap = word & TRAP != 0;
+        self.interrupt_enable = word & INTERRUPT_ENABLE != 0;
+        self.overflow = word & OVERFLOW != 0;
+    }
 }
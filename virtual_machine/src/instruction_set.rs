@@ -8,6 +8,7 @@ use crate::flags::Flags;
 use crate::instruction_set::Immediate::*;
 use crate::intrinsics::known_types::MARKER_TRAITS;
 use crate::memory::Scope;
+use crate::registers::SpecialRegister;
 use crate::resolution::functions::Function;
 use crate::resolution::types::descriptor::Variant;
 use crate::resolution::{Resolvable, FullIdentifier};
@@ -15,6 +16,8 @@ use crate::vm::Fault::{InvalidRegister, TypeMismatch};
 use crate::vm::{Fault, VirtualMachine, POINTER_SIZE};
 
 pub mod arithmetic;
+mod bitwise;
+mod conversion;
 mod immediate;
 
 #[derive(Debug, Copy, Clone)]
@@ -27,6 +30,69 @@ pub enum Operation {
     And,
     Or,
     Xor,
+    ShiftLeft,
+    /// Shifts right, filling with zeros
+    ShiftRight,
+    /// Shifts right, filling with copies of the sign bit
+    ArithmeticShiftRight,
+    RotateLeft,
+    RotateRight,
+    /// Unsigned for integers
+    Minimum,
+    /// Unsigned for integers
+    Maximum,
+    Power,
+}
+
+/// An operation on the value on top of the stack
+#[derive(Debug, Copy, Clone)]
+pub enum UnaryOperation {
+    /// Two's complement negation for integers
+    Negate,
+    /// Bitwise not
+    Not,
+    /// Pushes true if the value is zero, and false otherwise
+    LogicalNot,
+    PopCount,
+    LeadingZeros,
+    TrailingZeros,
+    /// Only for floats
+    SquareRoot,
+    /// Only for floats
+    Floor,
+    /// Only for floats
+    Ceiling,
+    /// Treats integers as two's complement
+    Absolute,
+}
+
+/// How an arithmetic operation handles a result that does not fit in its type. A left shift
+/// overflows when it shifts out a set bit, and honors the mode like arithmetic does. Other
+/// bitwise operations, right shifts and rotates can not overflow, and ignore the mode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ArithmeticMode {
+    /// The result wraps around, and the carry flag records whether it did
+    Wrapping,
+    /// Results that do not fit fault with `Fault::ArithmeticOverflow`
+    Checked,
+    /// Results that do not fit are clamped to the range of the type, and the carry flag records
+    /// whether they were
+    Saturating,
+}
+
+/// How a value is converted to another numeric type or char
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ConversionKind {
+    /// Converts like `as`: integers keep their low bits, floats are rounded toward zero and clamped
+    /// when converted to integers, and conversions to floats round to the nearest float.
+    /// Code points that are not chars fault with `Fault::InvalidConversion`.
+    Truncating,
+    /// Values that can not be represented exactly fault with `Fault::InvalidConversion`
+    Checked,
+    /// Values are clamped to the range of the type. Surrogates become the char below them.
+    Saturating,
+    /// The bits are reused as is, between types of the same width
+    Reinterpret,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -72,6 +138,33 @@ pub enum JumpType {
     NotOverflow,
     Signed,
     NotSigned,
+    /// Jumps if the low byte of the result has an even number of set bits
+    Parity,
+    NotParity,
+}
+
+impl JumpType {
+    /// Whether the jump is taken with these flags, which follows the x86 conditions
+    pub fn is_taken(&self, flags: &Flags) -> bool {
+        match self {
+            JumpType::Zero | JumpType::Equal => flags.zero,
+            JumpType::NotZero | JumpType::NotEqual => !flags.zero,
+            JumpType::Greater => !flags.zero && flags.sign == flags.overflow,
+            JumpType::GreaterEqual => flags.sign == flags.overflow,
+            JumpType::Above => !flags.carry && !flags.zero,
+            JumpType::AboveEqual => !flags.carry,
+            JumpType::Lesser => flags.sign != flags.overflow,
+            JumpType::LessEqual => flags.zero || flags.sign != flags.overflow,
+            JumpType::Below => flags.carry,
+            JumpType::BelowEqual => flags.carry || flags.zero,
+            JumpType::Overflow => flags.overflow,
+            JumpType::NotOverflow => !flags.overflow,
+            JumpType::Signed => flags.sign,
+            JumpType::NotSigned => !flags.sign,
+            JumpType::Parity => flags.parity,
+            JumpType::NotParity => !flags.parity,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -79,6 +172,8 @@ pub enum JumpType {
 pub enum RegisterType {
     Caller,
     Callee,
+    /// See [`SpecialRegister`](crate::registers::SpecialRegister)
+    Special,
 }
 
 #[derive(Debug, Clone)]
@@ -91,6 +186,9 @@ pub enum Literal {
     Immediate(Immediate),
     /// pop the top of the stack
     Peak,
+    /// An argument the caller spilled onto the stack, counting from the one closest to the
+    /// return address. Only available under a managed calling convention.
+    Argument(u8),
 }
 
 impl Literal {
@@ -106,6 +204,14 @@ impl Literal {
         Literal::Variable(loc.to_string())
     }
 
+    pub fn special(register: SpecialRegister) -> Literal {
+        Literal::Register(RegisterType::Special, register as u8)
+    }
+
+    pub fn is_special(&self, register: SpecialRegister) -> bool {
+        matches!(self, Literal::Register(RegisterType::Special, num) if *num == register as u8)
+    }
+
     pub fn get_immediate(&self, virtual_machine: &VirtualMachine) -> Result<Immediate, Fault> {
         match self {
             Literal::Variable(name) => virtual_machine.memory.get_variable(name),
@@ -114,17 +220,19 @@ impl Literal {
                     .registers
                     .caller
                     .get(*num as usize)
-                    .map(|i| i.clone())
+                    .cloned()
                     .ok_or(InvalidRegister),
                 RegisterType::Callee => virtual_machine
                     .registers
                     .callee
                     .get(*num as usize)
-                    .map(|i| i.clone())
+                    .cloned()
                     .ok_or(InvalidRegister),
+                RegisterType::Special => virtual_machine.read_special(*num),
             },
             Literal::Immediate(im) => Ok(im.clone()),
-            Literal::Peak => virtual_machine.peak().map(|imm| imm.clone()),
+            Literal::Peak => virtual_machine.peak().cloned(),
+            Literal::Argument(num) => virtual_machine.argument(*num).cloned(),
         }
     }
 
@@ -145,9 +253,11 @@ impl Literal {
                     .callee
                     .get(*num as usize)
                     .ok_or(InvalidRegister),
+                RegisterType::Special => Err(Fault::InvalidAddressOfLocation(Box::new(self.clone()))),
             },
-            Literal::Immediate(_) => Err(Fault::InvalidAddressOfLocation(self.clone())),
+            Literal::Immediate(_) => Err(Fault::InvalidAddressOfLocation(Box::new(self.clone()))),
             Literal::Peak => virtual_machine.peak(),
+            Literal::Argument(num) => virtual_machine.argument(*num),
         }
     }
 
@@ -168,9 +278,11 @@ impl Literal {
                     .callee
                     .get_mut(*num as usize)
                     .ok_or(InvalidRegister),
+                RegisterType::Special => Err(Fault::InvalidAddressOfLocation(Box::new(self.clone()))),
             },
-            Literal::Immediate(_) => Err(Fault::InvalidAddressOfLocation(self.clone())),
+            Literal::Immediate(_) => Err(Fault::InvalidAddressOfLocation(Box::new(self.clone()))),
             Literal::Peak => virtual_machine.peak_mut(),
+            Literal::Argument(num) => virtual_machine.argument_mut(*num),
         }
     }
 
@@ -191,9 +303,11 @@ impl Literal {
                     .callee
                     .get_mut(*num as usize)
                     .ok_or(InvalidRegister),
+                RegisterType::Special => Err(Fault::InvalidAddressOfLocation(Box::new(self.clone()))),
             },
             Literal::Immediate(im) => Ok(im),
             Literal::Peak => virtual_machine.peak_mut(),
+            Literal::Argument(num) => virtual_machine.argument_mut(*num),
         }
     }
 
@@ -361,7 +475,11 @@ pub enum Instruction {
     Ret(Option<Literal>),
     Jump(usize),
     Compare(ComparisonOperation),
-    PerformOperation(Operation),
+    PerformOperation(Operation, ArithmeticMode),
+    PerformUnaryOperation(UnaryOperation),
+    /// Pops three floats, and pushes the first times the second plus the third, rounded once
+    FusedMultiplyAdd,
+    /// Jumps if the condition holds for the current flags, leaving the stack untouched
     ConditionalJump(JumpType, usize),
     AddressOf(Literal),
     Dereference,
@@ -381,9 +499,15 @@ pub enum Instruction {
     DeclareVar(String, Scope),
     GetVar(String),
     SaveVar(String),
+    /// Converts the value on top of the stack to the type of `dest_type` like an `as` cast
     Coerce {
         dest_type: Immediate,
     },
+    /// Converts the value on top of the stack to the type of `dest_type`
+    Convert {
+        dest_type: Immediate,
+        kind: ConversionKind,
+    },
     CallFunction(Function),
     GetField(Literal, FullIdentifier),
     GetMember(Literal, usize),
@@ -395,4 +519,152 @@ pub enum Instruction {
     Lower,
     Exit,
     Heapify,
+    /// Pops a `USize` code address, a `Function`, or a `Closure`, and calls it.
+    ///
+    /// A closure is entered in a new local scope holding its captured variables, which it must
+    /// `Exit` before it returns.
+    CallIndirect,
+    /// Pops a `USize` code address or a `Function`, and jumps to it
+    JumpIndirect,
+    /// Moves the named local variables onto the heap, and pushes a closure of the function over
+    /// them
+    MakeClosure {
+        function: Function,
+        captures: Vec<String>,
+    },
+    /// Jumps to a procedure in place of the current one, which returns straight to the caller of
+    /// the current procedure. The return address is reused, and the local scope of the current
+    /// procedure is exited. Faults unless the current procedure entered its own local scope and
+    /// left only the return address on the value stack.
+    TailCall(usize),
+    /// A `TailCall` to a function
+    TailCallFunction(Function),
+    /// Starts a new execution context at an address, and pushes its `USize` id. The new context
+    /// starts with a copy of the caller registers, and only shares static memory.
+    Spawn(usize),
+    /// Lets every other ready context run before this one continues
+    Yield,
+    /// Waits for the context whose id is on top of the stack to halt, and replaces the id with
+    /// the value the context halted with
+    Join,
+    /// Pushes the `USize` id of a new channel
+    MakeChannel,
+    /// Pops a value, and then a channel id, and sends the value over the channel
+    Send,
+    /// Waits for a value on the channel whose id is on top of the stack, and replaces the id with
+    /// the value
+    Receive,
+    /// Pops a value, and stores it in the shared heap
+    SharedStore(String),
+    /// Pushes a copy of a value from the shared heap
+    SharedLoad(String),
+    /// Pops a new value, and then an expected value. The new value is stored in the shared heap
+    /// if the current value is the expected one, which sets the zero flag. The value from before
+    /// the exchange is pushed.
+    SharedCompareSwap(String),
+    /// Calls a function exported by a loaded module, which is looked up by name the first time
+    /// the call is made
+    CallSymbol(FullIdentifier),
+    /// Sets the interrupt enable flag
+    EnableInterrupts,
+    /// Clears the interrupt enable flag, so raised interrupts stay pending
+    DisableInterrupts,
+    /// Returns from an interrupt handler to where the interrupt happened, restoring the flags
+    ReturnFromInterrupt,
+}
+
+impl Instruction {
+    /// The absolute instruction address this instruction can transfer control to, if any
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            Instruction::Jump(target)
+            | Instruction::ConditionalJump(_, target)
+            | Instruction::Call(target)
+            | Instruction::TailCall(target)
+            | Instruction::Spawn(target) => Some(*target),
+            _ => None,
+        }
+    }
+
+    pub fn jump_target_mut(&mut self) -> Option<&mut usize> {
+        match self {
+            Instruction::Jump(target)
+            | Instruction::ConditionalJump(_, target)
+            | Instruction::Call(target)
+            | Instruction::TailCall(target)
+            | Instruction::Spawn(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    /// Whether execution can continue onto the next instruction after this one
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self,
+            Instruction::Jump(_)
+                | Instruction::JumpIndirect
+                | Instruction::TailCall(_)
+                | Instruction::TailCallFunction(_)
+                | Instruction::Ret(_)
+                | Instruction::ReturnFromInterrupt
+                | Instruction::Halt
+        ) && !self.writes_program_counter()
+    }
+
+    /// Whether this instruction calls a procedure that will return to the next instruction
+    pub fn is_call(&self) -> bool {
+        matches!(
+            self,
+            Instruction::Call(_)
+                | Instruction::CallFunction(_)
+                | Instruction::CallIndirect
+                | Instruction::CallSymbol(_)
+        )
+    }
+
+    /// Every location this instruction reads from or writes to
+    pub fn literals(&self) -> Vec<&Literal> {
+        match self {
+            Instruction::PopTo(literal)
+            | Instruction::Ret(Some(literal))
+            | Instruction::AddressOf(literal)
+            | Instruction::Push { src: literal }
+            | Instruction::GetField(literal, _)
+            | Instruction::GetMember(literal, _) => vec![literal],
+            Instruction::Move { dest, src } => vec![dest, src],
+            _ => vec![],
+        }
+    }
+
+    fn writes_program_counter(&self) -> bool {
+        match self {
+            Instruction::PopTo(dest) | Instruction::Move { dest, .. } => {
+                dest.is_special(SpecialRegister::ProgramCounter)
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether this instruction handles code addresses as values, so moving instructions around
+    /// could change where the program goes
+    pub fn uses_code_addresses(&self) -> bool {
+        match self {
+            Instruction::CallIndirect | Instruction::JumpIndirect => true,
+            _ => self
+                .literals()
+                .iter()
+                .any(|literal| literal.is_special(SpecialRegister::ProgramCounter)),
+        }
+    }
+
+    /// Whether this instruction could observe the flags
+    pub fn reads_flags(&self) -> bool {
+        match self {
+            Instruction::ConditionalJump(..) => true,
+            _ => self
+                .literals()
+                .iter()
+                .any(|literal| literal.is_special(SpecialRegister::Flags)),
+        }
+    }
 }
@@ -1,3 +1,7 @@
+use super::bitwise::{
+    amount, integer_bits, leading_zeros, pop_count, power, rotate, shift_left, shift_right,
+    trailing_zeros, with_bits, Shifted,
+};
 use super::*;
 
 pub struct OverflowingResult(pub Result<(Immediate, bool), Fault>);
@@ -43,7 +47,7 @@ impl Add<Immediate> for Immediate {
             (U64(v1), U64(v2)) => v1.overflowing_add(v2).into(),
             (USize(v1), USize(v2)) => v1.overflowing_add(v2).into(),
             (Float(v1), Float(v2)) => OverflowingResult(Ok((Float(v1 + v2), false))),
-            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 + v2), true))),
+            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 + v2), false))),
             _ => OverflowingResult(Err(Fault::PrimitiveTypeMismatch)),
         }
     }
@@ -60,7 +64,7 @@ impl Sub<Immediate> for Immediate {
             (U64(v1), U64(v2)) => v1.overflowing_sub(v2).into(),
             (USize(v1), USize(v2)) => v1.overflowing_sub(v2).into(),
             (Float(v1), Float(v2)) => OverflowingResult(Ok((Float(v1 - v2), false))),
-            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 - v2), true))),
+            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 - v2), false))),
             _ => OverflowingResult(Err(Fault::PrimitiveTypeMismatch)),
         }
     }
@@ -77,7 +81,7 @@ impl Mul<Immediate> for Immediate {
             (U64(v1), U64(v2)) => v1.overflowing_mul(v2).into(),
             (USize(v1), USize(v2)) => v1.overflowing_mul(v2).into(),
             (Float(v1), Float(v2)) => OverflowingResult(Ok((Float(v1 * v2), false))),
-            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 * v2), true))),
+            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 * v2), false))),
             _ => OverflowingResult(Err(Fault::PrimitiveTypeMismatch)),
         }
     }
@@ -94,7 +98,7 @@ impl Div<Immediate> for Immediate {
             (U64(v1), U64(v2)) => v1.overflowing_div(v2).into(),
             (USize(v1), USize(v2)) => v1.overflowing_div(v2).into(),
             (Float(v1), Float(v2)) => OverflowingResult(Ok((Float(v1 / v2), false))),
-            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 / v2), true))),
+            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 / v2), false))),
             _ => OverflowingResult(Err(Fault::PrimitiveTypeMismatch)),
         }
     }
@@ -111,7 +115,7 @@ impl Rem<Immediate> for Immediate {
             (U64(v1), U64(v2)) => v1.overflowing_rem(v2).into(),
             (USize(v1), USize(v2)) => v1.overflowing_rem(v2).into(),
             (Float(v1), Float(v2)) => OverflowingResult(Ok((Float(v1 % v2), false))),
-            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 % v2), true))),
+            (Double(v1), Double(v2)) => OverflowingResult(Ok((Double(v1 % v2), false))),
             _ => OverflowingResult(Err(Fault::PrimitiveTypeMismatch)),
         }
     }
@@ -162,72 +166,425 @@ impl BitXor<Immediate> for Immediate {
     }
 }
 
+/// Whether an integer operation would divide by zero
+fn divides_by_zero(divisor: &Immediate) -> bool {
+    match divisor {
+        Float(_) | Double(_) => false,
+        divisor => divisor.is_zero(),
+    }
+}
+
+/// Whether adding two integers of the same width, or subtracting the second from the first when
+/// `subtract`, gives a result that does not fit as two's complement
+fn signed_overflow(val1: &Immediate, val2: &Immediate, ret: &Immediate, subtract: bool) -> bool {
+    match (integer_bits(val1), integer_bits(val2), integer_bits(ret)) {
+        (Some((v1, bits)), Some((v2, _)), Some((ret, _))) => {
+            let negative = |value: u64| value >> (bits - 1) & 1 == 1;
+            let same_sign = negative(v1) == negative(v2);
+            same_sign != subtract && negative(ret) != negative(v1)
+        }
+        _ => false,
+    }
+}
+
+/// The value a saturating operation clamps to, being the largest value of the type when
+/// `upward`, or otherwise the smallest
+fn saturated(imm: &Immediate, upward: bool) -> Immediate {
+    match imm {
+        U8(_) => U8(if upward { u8::MAX } else { 0 }),
+        U16(_) => U16(if upward { u16::MAX } else { 0 }),
+        U32(_) => U32(if upward { u32::MAX } else { 0 }),
+        U64(_) => U64(if upward { u64::MAX } else { 0 }),
+        USize(_) => USize(if upward { usize::MAX } else { 0 }),
+        imm => imm.clone(),
+    }
+}
+
 impl Operation {
     pub fn perform_op(
         &self,
+        mode: ArithmeticMode,
         flags: &mut Flags,
         val1: Immediate,
         val2: Immediate,
     ) -> Result<Immediate, Fault> {
-        use Ordering::*;
+        if let Operation::Divide | Operation::Remainder = self {
+            if divides_by_zero(&val2) {
+                return Err(Fault::DivideByZero);
+            }
+        }
         let ret = match self {
             Operation::Add => {
-                let cmp1 = val1.zero_compare();
-                let cmp2 = val2.zero_compare();
-                let (ret, overflow): (Immediate, bool) = (val1 + val2).0?;
+                let (ret, overflow): (Immediate, bool) = (val1.clone() + val2.clone()).0?;
                 flags.carry = overflow;
-                flags.overflow = match (cmp1, cmp2, ret.zero_compare()) {
-                    (Some(Greater), Some(Greater), Some(Less))
-                    | (Some(Greater), Some(Greater), Some(Equal)) => true,
-                    (Some(Less), Some(Less), Some(Greater))
-                    | (Some(Less), Some(Less), Some(Equal)) => true,
-                    _ => false,
-                };
-                flags.sign = !ret.msb();
-
-                ret
+                flags.overflow = signed_overflow(&val1, &val2, &ret, false);
+                Self::apply_mode(mode, ret, overflow, true)?
             }
             Operation::Subtract => {
-                let (ret, overflow): (Immediate, bool) = (val1 - val2).0?;
+                let (ret, overflow): (Immediate, bool) = (val1.clone() - val2.clone()).0?;
                 flags.carry = overflow;
-                flags.sign = !ret.msb();
-                ret
+                flags.overflow = signed_overflow(&val1, &val2, &ret, true);
+                Self::apply_mode(mode, ret, overflow, false)?
             }
             Operation::Multiply => {
                 let (ret, overflow): (Immediate, bool) = (val1 * val2).0?;
                 flags.carry = overflow;
-                flags.sign = !ret.msb();
-                ret
+                flags.overflow = overflow;
+                Self::apply_mode(mode, ret, overflow, true)?
             }
             Operation::Divide => {
-                let (ret, overflow): (Immediate, bool) = (val1 / val2).0?;
-                flags.carry = overflow;
-                flags.sign = !ret.msb();
+                let (ret, _): (Immediate, bool) = (val1 / val2).0?;
+                flags.carry = false;
+                flags.overflow = false;
                 ret
             }
             Operation::Remainder => {
-                let (ret, overflow): (Immediate, bool) = (val1 % val2).0?;
-                flags.carry = overflow;
-                flags.sign = !ret.msb();
+                let (ret, _): (Immediate, bool) = (val1 % val2).0?;
+                flags.carry = false;
+                flags.overflow = false;
                 ret
             }
             Operation::And => {
                 let ret: Immediate = (val1 & val2)?;
-
+                flags.carry = false;
+                flags.overflow = false;
                 ret
             }
             Operation::Or => {
                 let ret: Immediate = (val1 | val2)?;
-
+                flags.carry = false;
+                flags.overflow = false;
                 ret
             }
             Operation::Xor => {
                 let ret: Immediate = (val1 ^ val2)?;
-
+                flags.carry = false;
+                flags.overflow = false;
+                ret
+            }
+            Operation::ShiftLeft
+            | Operation::ShiftRight
+            | Operation::ArithmeticShiftRight
+            | Operation::RotateLeft
+            | Operation::RotateRight => {
+                let shifted: Shifted = match self {
+                    Operation::ShiftLeft => shift_left(&val1, amount(&val2)?)?,
+                    Operation::ShiftRight => shift_right(&val1, amount(&val2)?, true)?,
+                    Operation::ArithmeticShiftRight => shift_right(&val1, amount(&val2)?, false)?,
+                    Operation::RotateLeft => rotate(&val1, &val2, true)?,
+                    _ => rotate(&val1, &val2, false)?,
+                };
+                flags.carry = shifted.carry;
+                flags.overflow = false;
+                Self::apply_mode(mode, shifted.value, shifted.lost, true)?
+            }
+            Operation::Minimum | Operation::Maximum => {
+                let maximum = matches!(self, Operation::Maximum);
+                let ret = match (&val1, &val2) {
+                    (Float(v1), Float(v2)) => {
+                        Float(if maximum { v1.max(*v2) } else { v1.min(*v2) })
+                    }
+                    (Double(v1), Double(v2)) => {
+                        Double(if maximum { v1.max(*v2) } else { v1.min(*v2) })
+                    }
+                    _ => match (integer_bits(&val1), integer_bits(&val2)) {
+                        (Some((v1, bits1)), Some((v2, bits2)))
+                            if bits1 == bits2
+                                && std::mem::discriminant(&val1)
+                                    == std::mem::discriminant(&val2) =>
+                        {
+                            with_bits(&val1, if maximum { v1.max(v2) } else { v1.min(v2) })
+                        }
+                        _ => return Err(Fault::PrimitiveTypeMismatch),
+                    },
+                };
+                flags.carry = false;
+                flags.overflow = false;
                 ret
             }
+            Operation::Power => {
+                let (ret, overflow): (Immediate, bool) = match (val1, val2) {
+                    (Float(v1), Float(v2)) => (Float(v1.powf(v2)), false),
+                    (Double(v1), Double(v2)) => (Double(v1.powf(v2)), false),
+                    (base, exp) => power(&base, &exp)?,
+                };
+                flags.carry = overflow;
+                flags.overflow = overflow;
+                Self::apply_mode(mode, ret, overflow, true)?
+            }
+        };
+        flags.set_result(&ret);
+        Ok(ret)
+    }
+
+    /// Handles the wrapped result of an operation according to the mode, where `upward` is the
+    /// direction the result overflowed in
+    fn apply_mode(
+        mode: ArithmeticMode,
+        ret: Immediate,
+        overflow: bool,
+        upward: bool,
+    ) -> Result<Immediate, Fault> {
+        match (mode, overflow) {
+            (_, false) | (ArithmeticMode::Wrapping, true) => Ok(ret),
+            (ArithmeticMode::Checked, true) => Err(Fault::ArithmeticOverflow),
+            (ArithmeticMode::Saturating, true) => Ok(saturated(&ret, upward)),
+        }
+    }
+}
+
+impl UnaryOperation {
+    pub fn perform_op(&self, flags: &mut Flags, val: Immediate) -> Result<Immediate, Fault> {
+        flags.carry = false;
+        flags.overflow = false;
+        let ret = match self {
+            UnaryOperation::Negate | UnaryOperation::Absolute => match val {
+                Float(d) if matches!(self, UnaryOperation::Negate) => Float(-d),
+                Double(d) if matches!(self, UnaryOperation::Negate) => Double(-d),
+                Float(d) => Float(d.abs()),
+                Double(d) => Double(d.abs()),
+                val => {
+                    let (value, bits) = integer_bits(&val).ok_or(Fault::PrimitiveTypeMismatch)?;
+                    let negative = value >> (bits - 1) == 1;
+                    if matches!(self, UnaryOperation::Absolute) && !negative {
+                        val
+                    } else {
+                        flags.carry = value != 0;
+                        flags.overflow = value == 1 << (bits - 1);
+                        with_bits(&val, value.wrapping_neg())
+                    }
+                }
+            },
+            UnaryOperation::Not => {
+                let (value, _) = integer_bits(&val).ok_or(Fault::PrimitiveTypeMismatch)?;
+                with_bits(&val, !value)
+            }
+            UnaryOperation::LogicalNot => Immediate::bool_equivalent(val.is_zero()),
+            UnaryOperation::PopCount => pop_count(&val)?,
+            UnaryOperation::LeadingZeros => leading_zeros(&val)?,
+            UnaryOperation::TrailingZeros => trailing_zeros(&val)?,
+            UnaryOperation::SquareRoot | UnaryOperation::Floor | UnaryOperation::Ceiling => {
+                let apply = |d: f64| match self {
+                    UnaryOperation::SquareRoot => d.sqrt(),
+                    UnaryOperation::Floor => d.floor(),
+                    _ => d.ceil(),
+                };
+                match val {
+                    Float(d) => Float(apply(d as f64) as f32),
+                    Double(d) => Double(apply(d)),
+                    _ => return Err(Fault::PrimitiveTypeMismatch),
+                }
+            }
         };
-        flags.zero = ret.is_zero();
+        flags.set_result(&ret);
         Ok(ret)
     }
 }
+
+/// Computes `val1 * val2 + val3` with a single rounding
+pub fn fused_multiply_add(
+    flags: &mut Flags,
+    val1: Immediate,
+    val2: Immediate,
+    val3: Immediate,
+) -> Result<Immediate, Fault> {
+    let ret = match (val1, val2, val3) {
+        (Float(v1), Float(v2), Float(v3)) => Float(v1.mul_add(v2, v3)),
+        (Double(v1), Double(v2), Double(v3)) => Double(v1.mul_add(v2, v3)),
+        _ => return Err(Fault::PrimitiveTypeMismatch),
+    };
+    flags.carry = false;
+    flags.overflow = false;
+    flags.set_result(&ret);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::flags::Flags;
+    use crate::instruction_set::arithmetic::fused_multiply_add;
+    use crate::instruction_set::Immediate::{Double, Float, U32, U8};
+    use crate::instruction_set::Immediate::{U16, U64};
+    use crate::instruction_set::{ArithmeticMode, Operation, UnaryOperation};
+    use crate::vm::Fault;
+
+    #[test]
+    fn arithmetic_modes() {
+        let mut flags = Flags::new();
+        let wrapped =
+            Operation::Add.perform_op(ArithmeticMode::Wrapping, &mut flags, U8(250), U8(10));
+        assert!(matches!(wrapped, Ok(U8(4))));
+        assert!(flags.carry);
+
+        let checked =
+            Operation::Add.perform_op(ArithmeticMode::Checked, &mut flags, U8(250), U8(10));
+        assert!(matches!(checked, Err(Fault::ArithmeticOverflow)));
+        let checked =
+            Operation::Add.perform_op(ArithmeticMode::Checked, &mut flags, U8(250), U8(5));
+        assert!(matches!(checked, Ok(U8(255))));
+
+        let saturated =
+            Operation::Multiply.perform_op(ArithmeticMode::Saturating, &mut flags, U8(20), U8(20));
+        assert!(matches!(saturated, Ok(U8(255))));
+        assert!(flags.carry);
+        let saturated =
+            Operation::Subtract.perform_op(ArithmeticMode::Saturating, &mut flags, U8(3), U8(5));
+        assert!(matches!(saturated, Ok(U8(0))));
+        assert!(flags.zero);
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let mut flags = Flags::new();
+        for mode in &[
+            ArithmeticMode::Wrapping,
+            ArithmeticMode::Checked,
+            ArithmeticMode::Saturating,
+        ] {
+            let quotient = Operation::Divide.perform_op(*mode, &mut flags, U32(1), U32(0));
+            assert!(matches!(quotient, Err(Fault::DivideByZero)));
+            let remainder = Operation::Remainder.perform_op(*mode, &mut flags, U32(1), U32(0));
+            assert!(matches!(remainder, Err(Fault::DivideByZero)));
+        }
+
+        let quotient = Operation::Divide.perform_op(
+            ArithmeticMode::Checked,
+            &mut flags,
+            Float(1.0),
+            Float(0.0),
+        );
+        assert!(matches!(quotient, Ok(Float(f)) if f.is_infinite()));
+    }
+
+    #[test]
+    fn floats_do_not_carry() {
+        let mut flags = Flags::new();
+        let sum = Operation::Add.perform_op(
+            ArithmeticMode::Checked,
+            &mut flags,
+            Double(f64::MAX),
+            Double(f64::MAX),
+        );
+        assert!(matches!(sum, Ok(Double(d)) if d.is_infinite()));
+        assert!(!flags.carry);
+    }
+
+    #[test]
+    fn shifts_and_rotates() {
+        let mut flags = Flags::new();
+        let wrapping = ArithmeticMode::Wrapping;
+        let shifted = Operation::ShiftLeft.perform_op(wrapping, &mut flags, U8(0b1100_0001), U8(2));
+        assert!(matches!(shifted, Ok(U8(0b0000_0100))));
+        assert!(flags.carry);
+        let shifted =
+            Operation::ShiftLeft.perform_op(ArithmeticMode::Checked, &mut flags, U8(0x81), U8(1));
+        assert!(matches!(shifted, Err(Fault::ArithmeticOverflow)));
+
+        let shifted = Operation::ShiftRight.perform_op(wrapping, &mut flags, U8(0x81), U8(1));
+        assert!(matches!(shifted, Ok(U8(0x40))));
+        assert!(flags.carry);
+        let shifted =
+            Operation::ArithmeticShiftRight.perform_op(wrapping, &mut flags, U8(0x84), U8(2));
+        assert!(matches!(shifted, Ok(U8(0xE1))));
+        let shifted =
+            Operation::ArithmeticShiftRight.perform_op(wrapping, &mut flags, U16(0x8000), U8(20));
+        assert!(matches!(shifted, Ok(U16(0xFFFF))));
+
+        let rotated = Operation::RotateLeft.perform_op(wrapping, &mut flags, U8(0x81), U8(1));
+        assert!(matches!(rotated, Ok(U8(0x03))));
+        assert!(flags.carry);
+        let rotated = Operation::RotateRight.perform_op(wrapping, &mut flags, U32(1), U8(33));
+        assert!(matches!(rotated, Ok(U32(0x8000_0000))));
+        assert!(flags.carry);
+    }
+
+    #[test]
+    fn shifts_honor_mode() {
+        let mut flags = Flags::new();
+        let checked = ArithmeticMode::Checked;
+        let shifted = Operation::ShiftLeft.perform_op(checked, &mut flags, U8(0x41), U8(1));
+        assert!(matches!(shifted, Ok(U8(0x82))));
+        let shifted = Operation::ShiftLeft.perform_op(
+            ArithmeticMode::Saturating,
+            &mut flags,
+            U8(0x81),
+            U8(1),
+        );
+        assert!(matches!(shifted, Ok(U8(0xFF))));
+
+        let shifted = Operation::ShiftRight.perform_op(checked, &mut flags, U8(0x81), U8(1));
+        assert!(matches!(shifted, Ok(U8(0x40))));
+        let rotated = Operation::RotateLeft.perform_op(checked, &mut flags, U8(0x81), U8(1));
+        assert!(matches!(rotated, Ok(U8(0x03))));
+        let anded = Operation::And.perform_op(checked, &mut flags, U8(0x81), U8(0xFF));
+        assert!(matches!(anded, Ok(U8(0x81))));
+    }
+
+    #[test]
+    fn unary_operations() {
+        let mut flags = Flags::new();
+        assert!(matches!(
+            UnaryOperation::Negate.perform_op(&mut flags, U8(1)),
+            Ok(U8(0xFF))
+        ));
+        assert!(matches!(
+            UnaryOperation::Negate.perform_op(&mut flags, U8(0x80)),
+            Ok(U8(0x80))
+        ));
+        assert!(flags.overflow);
+        assert!(matches!(
+            UnaryOperation::Absolute.perform_op(&mut flags, U8(0xFE)),
+            Ok(U8(2))
+        ));
+        assert!(matches!(
+            UnaryOperation::Not.perform_op(&mut flags, U16(0x00FF)),
+            Ok(U16(0xFF00))
+        ));
+        assert!(matches!(
+            UnaryOperation::PopCount.perform_op(&mut flags, U64(0xF0F0)),
+            Ok(U64(8))
+        ));
+        assert!(matches!(
+            UnaryOperation::LeadingZeros.perform_op(&mut flags, U16(1)),
+            Ok(U16(15))
+        ));
+        assert!(matches!(
+            UnaryOperation::TrailingZeros.perform_op(&mut flags, U8(0)),
+            Ok(U8(8))
+        ));
+        assert!(matches!(
+            UnaryOperation::SquareRoot.perform_op(&mut flags, Double(9.0)),
+            Ok(Double(d)) if d == 3.0
+        ));
+        assert!(matches!(
+            UnaryOperation::Floor.perform_op(&mut flags, Float(-1.5)),
+            Ok(Float(d)) if d == -2.0
+        ));
+        assert!(matches!(
+            UnaryOperation::Ceiling.perform_op(&mut flags, U8(1)),
+            Err(Fault::PrimitiveTypeMismatch)
+        ));
+    }
+
+    #[test]
+    fn math_operations() {
+        let mut flags = Flags::new();
+        let wrapping = ArithmeticMode::Wrapping;
+        let minimum = Operation::Minimum.perform_op(wrapping, &mut flags, U8(3), U8(200));
+        assert!(matches!(minimum, Ok(U8(3))));
+        let maximum =
+            Operation::Maximum.perform_op(wrapping, &mut flags, Double(-1.0), Double(2.0));
+        assert!(matches!(maximum, Ok(Double(d)) if d == 2.0));
+        let mismatch = Operation::Maximum.perform_op(wrapping, &mut flags, U8(1), U16(2));
+        assert!(matches!(mismatch, Err(Fault::PrimitiveTypeMismatch)));
+
+        let power = Operation::Power.perform_op(wrapping, &mut flags, U32(3), U8(4));
+        assert!(matches!(power, Ok(U32(81))));
+        let power =
+            Operation::Power.perform_op(ArithmeticMode::Saturating, &mut flags, U8(2), U8(8));
+        assert!(matches!(power, Ok(U8(255))));
+
+        let fused = fused_multiply_add(&mut flags, Double(2.0), Double(3.0), Double(1.0));
+        assert!(matches!(fused, Ok(Double(d)) if d == 7.0));
+    }
+}
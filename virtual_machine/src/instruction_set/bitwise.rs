@@ -0,0 +1,156 @@
+use super::*;
+
+/// Gets the value of an integer immediate, and how many bits wide its type is
+pub(crate) fn integer_bits(imm: &Immediate) -> Option<(u64, u32)> {
+    match imm {
+        U8(d) => Some((*d as u64, 8)),
+        U16(d) => Some((*d as u64, 16)),
+        U32(d) => Some((*d as u64, 32)),
+        U64(d) => Some((*d, 64)),
+        USize(d) => Some((*d as u64, (POINTER_SIZE * 8) as u32)),
+        _ => None,
+    }
+}
+
+/// Creates an immediate of the same type as `like`, from the low bits of `value`
+pub(crate) fn with_bits(like: &Immediate, value: u64) -> Immediate {
+    match like {
+        U8(_) => U8(value as u8),
+        U16(_) => U16(value as u16),
+        U32(_) => U32(value as u32),
+        U64(_) => U64(value),
+        USize(_) => USize(value as usize),
+        imm => imm.clone(),
+    }
+}
+
+pub(crate) fn mask(bits: u32) -> u64 {
+    if bits == 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Reads a shift amount or exponent, which can be any integer type
+pub(crate) fn amount(imm: &Immediate) -> Result<u32, Fault> {
+    match integer_bits(imm) {
+        Some((value, _)) => Ok(value.min(u32::MAX as u64) as u32),
+        None => Err(Fault::PrimitiveTypeMismatch),
+    }
+}
+
+/// Raises an integer to any integer power by squaring, wrapping at its width. Also gives whether
+/// the exact result did not fit.
+pub(crate) fn power(imm: &Immediate, exponent: &Immediate) -> Result<(Immediate, bool), Fault> {
+    let (mut base, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let (mut exponent, _) = integer_bits(exponent).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let limit = mask(bits) as u128;
+    let mut result = 1;
+    let mut overflow = false;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            let wide = result as u128 * base as u128;
+            overflow |= wide > limit;
+            result = (wide & limit) as u64;
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            // The square is a factor of the exact result, unless the base is zero
+            let wide = base as u128 * base as u128;
+            overflow |= wide > limit;
+            base = (wide & limit) as u64;
+        }
+    }
+    Ok((with_bits(imm, result), overflow))
+}
+
+/// The result of shifting or rotating an integer
+pub(crate) struct Shifted {
+    pub(crate) value: Immediate,
+    /// The last bit that was shifted out, or for rotates the bit that was moved across the end
+    pub(crate) carry: bool,
+    /// Whether any set bit was shifted out
+    pub(crate) lost: bool,
+}
+
+pub(crate) fn shift_left(imm: &Immediate, amount: u32) -> Result<Shifted, Fault> {
+    let (value, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let (shifted, carry, lost) = if amount == 0 {
+        (value, false, false)
+    } else if amount >= bits {
+        (0, amount == bits && value & 1 == 1, value != 0)
+    } else {
+        let out = value >> (bits - amount);
+        ((value << amount) & mask(bits), out & 1 == 1, out != 0)
+    };
+    Ok(Shifted {
+        value: with_bits(imm, shifted),
+        carry,
+        lost,
+    })
+}
+
+/// Shifts right, filling with zeros when `logical`, or otherwise with copies of the sign bit
+pub(crate) fn shift_right(imm: &Immediate, amount: u32, logical: bool) -> Result<Shifted, Fault> {
+    let (value, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let negative = !logical && value >> (bits - 1) == 1;
+    let fill = if negative { mask(bits) } else { 0 };
+    let (shifted, carry) = if amount == 0 {
+        (value, false)
+    } else if amount >= bits {
+        let carry = if amount == bits {
+            value >> (bits - 1) == 1
+        } else {
+            negative
+        };
+        (fill, carry)
+    } else {
+        let high = fill & !(mask(bits) >> amount);
+        ((value >> amount) | high, (value >> (amount - 1)) & 1 == 1)
+    };
+    Ok(Shifted {
+        value: with_bits(imm, shifted),
+        carry,
+        lost: false,
+    })
+}
+
+pub(crate) fn rotate(imm: &Immediate, amount: &Immediate, left: bool) -> Result<Shifted, Fault> {
+    let (value, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let (amount, _) = integer_bits(amount).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let amount = (amount % bits as u64) as u32;
+    let rotated = if amount == 0 {
+        value
+    } else if left {
+        ((value << amount) | (value >> (bits - amount))) & mask(bits)
+    } else {
+        ((value >> amount) | (value << (bits - amount))) & mask(bits)
+    };
+    let carry = if left {
+        rotated & 1 == 1
+    } else {
+        rotated >> (bits - 1) == 1
+    };
+    Ok(Shifted {
+        value: with_bits(imm, rotated),
+        carry,
+        lost: false,
+    })
+}
+
+pub(crate) fn pop_count(imm: &Immediate) -> Result<Immediate, Fault> {
+    let (value, _) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    Ok(with_bits(imm, value.count_ones() as u64))
+}
+
+pub(crate) fn leading_zeros(imm: &Immediate) -> Result<Immediate, Fault> {
+    let (value, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    let zeros = value.leading_zeros() - (64 - bits);
+    Ok(with_bits(imm, zeros as u64))
+}
+
+pub(crate) fn trailing_zeros(imm: &Immediate) -> Result<Immediate, Fault> {
+    let (value, bits) = integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?;
+    Ok(with_bits(imm, value.trailing_zeros().min(bits) as u64))
+}
@@ -6,6 +6,7 @@ impl PartialEq for Immediate {
             (U16(v1), U16(v2)) => v1 == v2,
             (U32(v1), U32(v2)) => v1 == v2,
             (U64(v1), U64(v2)) => v1 == v2,
+            (USize(v1), USize(v2)) => v1 == v2,
             (Float(v1), Float(v2)) => v1 == v2,
             (Double(v1), Double(v2)) => v1 == v2,
             (Pointer(v1), Pointer(v2)) => v1 == v2,
@@ -23,6 +24,7 @@ impl PartialOrd for Immediate {
             (U16(v1), U16(v2)) => v1.partial_cmp(v2),
             (U32(v1), U32(v2)) => v1.partial_cmp(v2),
             (U64(v1), U64(v2)) => v1.partial_cmp(v2),
+            (USize(v1), USize(v2)) => v1.partial_cmp(v2),
             (Float(v1), Float(v2)) => v1.partial_cmp(v2),
             (Double(v1), Double(v2)) => v1.partial_cmp(v2),
             (Pointer(v1), Pointer(v2)) => v1.partial_cmp(v2),
@@ -40,6 +42,7 @@ impl PartialEq for Signed {
             (U16(v1), U16(v2)) => (*v1 as i16).eq(&(*v2 as i16)),
             (U32(v1), U32(v2)) => (*v1 as i32).eq(&(*v2 as i32)),
             (U64(v1), U64(v2)) => (*v1 as i64).eq(&(*v2 as i64)),
+            (USize(v1), USize(v2)) => (*v1 as isize).eq(&(*v2 as isize)),
             (Float(v1), Float(v2)) => v1.eq(v2),
             (Double(v1), Double(v2)) => v1.eq(v2),
             (Pointer(v1), Pointer(v2)) => v1.eq(v2),
@@ -59,6 +62,7 @@ impl PartialOrd for Signed {
             (U16(v1), U16(v2)) => (*v1 as i16).partial_cmp(&(*v2 as i16)),
             (U32(v1), U32(v2)) => (*v1 as i32).partial_cmp(&(*v2 as i32)),
             (U64(v1), U64(v2)) => (*v1 as i64).partial_cmp(&(*v2 as i64)),
+            (USize(v1), USize(v2)) => (*v1 as isize).partial_cmp(&(*v2 as isize)),
             (Float(v1), Float(v2)) => v1.partial_cmp(v2),
             (Double(v1), Double(v2)) => v1.partial_cmp(v2),
             (Pointer(v1), Pointer(v2)) => v1.partial_cmp(v2),
@@ -172,14 +176,13 @@ impl ComparisonOperation {
                 .into())
             }
             ComparisonOperation::Compare => {
-                let sub: Immediate = (val1 - val2).0?.0;
-                Ok(sub)
+                return Operation::Subtract.perform_op(ArithmeticMode::Wrapping, flags, val1, val2);
             }
         };
         if let Ok(imm) = &ret {
-            flags.zero = imm.is_zero();
-            flags.sign = !imm.msb();
-            flags.parity = imm.set_bits() % 2 == 0;
+            flags.carry = false;
+            flags.overflow = false;
+            flags.set_result(imm);
         }
         ret
     }
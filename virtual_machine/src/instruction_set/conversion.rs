@@ -0,0 +1,250 @@
+use super::bitwise::{integer_bits, mask, with_bits};
+use super::*;
+
+/// The value of a numeric immediate, in a form that holds every one of them exactly
+#[derive(Debug, Copy, Clone)]
+enum Numeric {
+    Integer(u64),
+    Float(f64),
+}
+
+/// The first and last code points of the surrogates, which are not valid chars
+const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+
+impl Numeric {
+    fn of(imm: &Immediate) -> Result<Self, Fault> {
+        Ok(match imm {
+            Float(d) => Numeric::Float(*d as f64),
+            Double(d) => Numeric::Float(*d),
+            Char(d) => Numeric::Integer(*d as u64),
+            Pointer(d) => Numeric::Integer(*d as usize as u64),
+            PointerConst(d) => Numeric::Integer(*d as usize as u64),
+            imm => Numeric::Integer(integer_bits(imm).ok_or(Fault::PrimitiveTypeMismatch)?.0),
+        })
+    }
+
+    fn to_integer(self, bits: u32, kind: ConversionKind) -> Result<u64, Fault> {
+        let max = mask(bits);
+        match (self, kind) {
+            (Numeric::Integer(value), ConversionKind::Truncating) => Ok(value & max),
+            (Numeric::Integer(value), ConversionKind::Saturating) => Ok(value.min(max)),
+            (Numeric::Integer(value), _) if value > max => Err(Fault::InvalidConversion),
+            (Numeric::Integer(value), _) => Ok(value),
+            // Like `as`, the fraction is dropped and the value is clamped to the range of the type
+            (Numeric::Float(value), ConversionKind::Truncating)
+            | (Numeric::Float(value), ConversionKind::Saturating) => Ok((value as u64).min(max)),
+            (Numeric::Float(value), _) => {
+                if value.fract() != 0.0 || !(0.0..2f64.powi(bits as i32)).contains(&value) {
+                    Err(Fault::InvalidConversion)
+                } else {
+                    Ok(value as u64)
+                }
+            }
+        }
+    }
+
+    fn to_float(self, kind: ConversionKind) -> Result<f32, Fault> {
+        let (ret, exact) = match self {
+            Numeric::Integer(value) => {
+                let ret = value as f32;
+                (ret, ret as u128 == value as u128)
+            }
+            Numeric::Float(value) => {
+                let ret = value as f32;
+                if ret.is_infinite() && value.is_finite() && kind == ConversionKind::Saturating {
+                    return Ok(f32::MAX.copysign(ret));
+                }
+                (ret, value.is_nan() || ret as f64 == value)
+            }
+        };
+        if !exact && kind == ConversionKind::Checked {
+            return Err(Fault::InvalidConversion);
+        }
+        Ok(ret)
+    }
+
+    fn to_double(self, kind: ConversionKind) -> Result<f64, Fault> {
+        match self {
+            Numeric::Integer(value) => {
+                let ret = value as f64;
+                if kind == ConversionKind::Checked && ret as u128 != value as u128 {
+                    return Err(Fault::InvalidConversion);
+                }
+                Ok(ret)
+            }
+            Numeric::Float(value) => Ok(value),
+        }
+    }
+
+    fn to_char(self, kind: ConversionKind) -> Result<char, Fault> {
+        let value = match self {
+            Numeric::Integer(value) if kind == ConversionKind::Truncating => value as u32,
+            Numeric::Integer(value) if kind == ConversionKind::Saturating => {
+                let value = value.min(char::MAX as u64) as u32;
+                if value >= SURROGATES.0 && value <= SURROGATES.1 {
+                    SURROGATES.0 - 1
+                } else {
+                    value
+                }
+            }
+            numeric => numeric.to_integer(32, kind)? as u32,
+        };
+        std::char::from_u32(value).ok_or(Fault::InvalidConversion)
+    }
+}
+
+/// Gets the bits of a numeric immediate, and how many bits wide its type is
+fn raw_bits(imm: &Immediate) -> Option<(u64, u32)> {
+    match imm {
+        Float(d) => Some((d.to_bits() as u64, 32)),
+        Double(d) => Some((d.to_bits(), 64)),
+        Char(d) => Some((*d as u64, 32)),
+        imm => integer_bits(imm),
+    }
+}
+
+impl Immediate {
+    /// Converts a numeric immediate or char to the type of `dest_type`
+    pub fn convert(self, dest_type: &Immediate, kind: ConversionKind) -> Result<Immediate, Fault> {
+        if kind == ConversionKind::Reinterpret {
+            return self.reinterpret(dest_type);
+        }
+        let numeric = Numeric::of(&self)?;
+        Ok(match dest_type {
+            Float(_) => Float(numeric.to_float(kind)?),
+            Double(_) => Double(numeric.to_double(kind)?),
+            Char(_) => Char(numeric.to_char(kind)?),
+            dest => {
+                let (_, bits) = integer_bits(dest).ok_or(Fault::PrimitiveTypeMismatch)?;
+                with_bits(dest, numeric.to_integer(bits, kind)?)
+            }
+        })
+    }
+
+    fn reinterpret(self, dest_type: &Immediate) -> Result<Immediate, Fault> {
+        let (value, bits) = raw_bits(&self).ok_or(Fault::PrimitiveTypeMismatch)?;
+        match raw_bits(dest_type) {
+            Some((_, dest_bits)) if dest_bits == bits => {}
+            _ => return Err(Fault::PrimitiveTypeMismatch),
+        }
+        Ok(match dest_type {
+            Float(_) => Float(f32::from_bits(value as u32)),
+            Double(_) => Double(f64::from_bits(value)),
+            Char(_) => Char(std::char::from_u32(value as u32).ok_or(Fault::InvalidConversion)?),
+            dest => with_bits(dest, value),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::instruction_set::ConversionKind::{Checked, Reinterpret, Saturating, Truncating};
+    use crate::instruction_set::Immediate::{Char, Double, Float, U16, U32, U64, U8};
+    use crate::vm::Fault;
+
+    #[test]
+    fn integer_conversions() {
+        assert_eq!(U16(300).convert(&U8(0), Truncating).unwrap(), U8(44));
+        assert_eq!(U16(300).convert(&U8(0), Saturating).unwrap(), U8(255));
+        assert!(matches!(
+            U16(300).convert(&U8(0), Checked),
+            Err(Fault::InvalidConversion)
+        ));
+        assert_eq!(U16(200).convert(&U8(0), Checked).unwrap(), U8(200));
+        assert_eq!(U8(200).convert(&U64(0), Checked).unwrap(), U64(200));
+    }
+
+    #[test]
+    fn float_conversions() {
+        assert_eq!(Double(-2.5).convert(&U32(0), Truncating).unwrap(), U32(0));
+        assert_eq!(
+            Double(1e12).convert(&U32(0), Saturating).unwrap(),
+            U32(u32::MAX)
+        );
+        assert!(Double(2.5).convert(&U32(0), Checked).is_err());
+        assert!(Double(f64::NAN).convert(&U32(0), Checked).is_err());
+        assert_eq!(Double(7.0).convert(&U32(0), Checked).unwrap(), U32(7));
+
+        assert!(U64(u64::MAX).convert(&Double(0.0), Checked).is_err());
+        assert_eq!(U32(16).convert(&Float(0.0), Checked).unwrap(), Float(16.0));
+        assert!(Double(0.1).convert(&Float(0.0), Checked).is_err());
+        assert_eq!(
+            Double(1e300).convert(&Float(0.0), Truncating).unwrap(),
+            Float(f32::INFINITY)
+        );
+        assert_eq!(
+            Double(-1e300).convert(&Float(0.0), Saturating).unwrap(),
+            Float(-f32::MAX)
+        );
+    }
+
+    #[test]
+    fn truncating_to_floats_rounds_like_as() {
+        // The nearest float to each of these is further from zero than they are
+        let above_one = 1.0 + 2f64.powi(-24) + 2f64.powi(-30);
+        assert_eq!(
+            Double(above_one).convert(&Float(0.0), Truncating).unwrap(),
+            Float(above_one as f32)
+        );
+        assert_eq!(
+            Double(-above_one).convert(&Float(0.0), Truncating).unwrap(),
+            Float(-above_one as f32)
+        );
+        assert_eq!(
+            U64(u64::MAX).convert(&Float(0.0), Truncating).unwrap(),
+            Float(u64::MAX as f32)
+        );
+        assert_eq!(
+            U64(u64::MAX).convert(&Double(0.0), Truncating).unwrap(),
+            Double(u64::MAX as f64)
+        );
+        assert_eq!(
+            U32(u32::MAX).convert(&Float(0.0), Truncating).unwrap(),
+            Float(u32::MAX as f32)
+        );
+    }
+
+    #[test]
+    fn char_conversions() {
+        assert!(matches!(
+            U32(0x1F600).convert(&Char('\0'), Checked),
+            Ok(Char('😀'))
+        ));
+        assert!(matches!(
+            U32(0xD800).convert(&Char('\0'), Truncating),
+            Err(Fault::InvalidConversion)
+        ));
+        assert!(matches!(
+            U32(0xD800).convert(&Char('\0'), Saturating),
+            Ok(Char('\u{D7FF}'))
+        ));
+        assert!(matches!(
+            U64(u64::MAX).convert(&Char('\0'), Saturating),
+            Ok(Char(char::MAX))
+        ));
+        assert_eq!(Char('é').convert(&U8(0), Checked).unwrap(), U8(0xE9));
+        assert!(Char('😀').convert(&U8(0), Checked).is_err());
+    }
+
+    #[test]
+    fn reinterpreting() {
+        assert_eq!(
+            Float(1.0).convert(&U32(0), Reinterpret).unwrap(),
+            U32(0x3F80_0000)
+        );
+        assert_eq!(
+            U64(0x4000_0000_0000_0000)
+                .convert(&Double(0.0), Reinterpret)
+                .unwrap(),
+            Double(2.0)
+        );
+        assert!(matches!(
+            U32(0x41).convert(&Char('\0'), Reinterpret),
+            Ok(Char('A'))
+        ));
+        assert!(matches!(
+            Float(1.0).convert(&U64(0), Reinterpret),
+            Err(Fault::PrimitiveTypeMismatch)
+        ));
+    }
+}
@@ -3,7 +3,7 @@ use std::convert::TryFrom;
 
 use Immediate::*;
 
-use crate::resolution::functions::Function;
+use crate::resolution::functions::{Closure, Function};
 use crate::resolution::types::descriptor::Variant;
 use crate::resolution::types::TypedObject;
 use crate::vm::{Fault, POINTER_SIZE};
@@ -42,6 +42,8 @@ pub enum Immediate {
     /// A type with more information stored in it
     DetailedType(TypedObject),
     Function(Function),
+    /// A function with its captured environment
+    Closure(Closure),
 }
 
 macro_rules! into_other_primitive {
@@ -204,7 +206,7 @@ impl Immediate {
         if input {
             U8(0)
         } else {
-            U8(std::u8::MAX)
+            U8(u8::MAX)
         }
     }
     /// Gets the most significant bit
@@ -215,6 +217,8 @@ impl Immediate {
             U32(d) => d >> 31 > 0,
             U64(d) => d >> 63 > 0,
             USize(d) => d >> (if POINTER_SIZE == 4 { 31 } else { 63 }) > 0,
+            Float(d) => d.is_sign_negative(),
+            Double(d) => d.is_sign_negative(),
             Char(d) => *d as u8 >> 7 > 0,
             _ => {
                 panic!("{:?}", Fault::PrimitiveTypeMismatch);
@@ -255,6 +259,7 @@ impl Immediate {
             Variant(_) => false,
             DetailedType(details) => unimplemented!(),
             Function(_) => false,
+            Closure(_) => false,
             _ => true,
         }
     }
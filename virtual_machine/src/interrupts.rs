@@ -0,0 +1,107 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::vm::Fault;
+
+/// The interrupt raised each time the timer runs out
+pub const TIMER_INTERRUPT: u8 = 0;
+/// The interrupt raised after every instruction that runs while the trap flag is set
+pub const TRAP_INTERRUPT: u8 = 1;
+/// How many different interrupts there are
+pub const INTERRUPT_COUNT: u8 = 64;
+
+/// Raises interrupts on a virtual machine, possibly from another thread.
+///
+/// Raised interrupts stay pending until the virtual machine has interrupts enabled, and are then
+/// handled lowest number first. Raising an interrupt that is already pending has no effect.
+#[derive(Clone, Default)]
+pub struct InterruptHandle {
+    pending: Arc<AtomicU64>,
+}
+
+impl InterruptHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn raise(&self, interrupt: u8) -> Result<(), Fault> {
+        if interrupt >= INTERRUPT_COUNT {
+            return Err(Fault::InvalidInterrupt(interrupt));
+        }
+        self.pending.fetch_or(1 << interrupt, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub fn is_pending(&self, interrupt: u8) -> bool {
+        interrupt < INTERRUPT_COUNT && self.pending.load(Ordering::SeqCst) & (1 << interrupt) != 0
+    }
+
+    /// Removes the lowest pending interrupt
+    pub(crate) fn take(&self) -> Option<u8> {
+        let mut pending = self.pending.load(Ordering::SeqCst);
+        while pending != 0 {
+            let interrupt = pending.trailing_zeros();
+            match self.pending.compare_exchange(
+                pending,
+                pending & !(1 << interrupt),
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(interrupt as u8),
+                Err(current) => pending = current,
+            }
+        }
+        None
+    }
+}
+
+/// The state saved when an interrupt handler is entered, which is restored when it returns
+pub(crate) struct InterruptFrame {
+    pub(crate) program_counter: usize,
+    pub(crate) flags: u16,
+    /// The handler must leave the value stack as deep as it found it
+    pub(crate) stack_depth: usize,
+}
+
+/// Raises the timer interrupt every `interval` instructions
+pub(crate) struct Timer {
+    pub(crate) interval: usize,
+    pub(crate) remaining: usize,
+}
+
+impl Timer {
+    pub(crate) fn new(interval: usize) -> Self {
+        Timer {
+            interval,
+            remaining: interval,
+        }
+    }
+
+    /// Counts an instruction, and gets whether the timer ran out
+    pub(crate) fn tick(&mut self) -> bool {
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.remaining = self.interval;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::interrupts::InterruptHandle;
+
+    #[test]
+    fn lowest_interrupt_first() {
+        let handle = InterruptHandle::new();
+        handle.raise(9).unwrap();
+        handle.raise(3).unwrap();
+        handle.raise(9).unwrap();
+        assert!(handle.raise(64).is_err());
+        assert_eq!(handle.take(), Some(3));
+        assert_eq!(handle.take(), Some(9));
+        assert_eq!(handle.take(), None);
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+
+use crate::instruction_set::{Immediate, Instruction, Literal};
+use crate::resolution::functions::Function;
+use crate::resolution::types::descriptor::{StorageType, TypeDescriptor, Variant};
+use crate::vm::{Fault, VirtualMachine};
+
+/// A value that can be sent between isolates, because it is plain data without any pointers
+#[derive(Debug, Clone, PartialEq)]
+pub enum SharedValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    USize(usize),
+    Float(f32),
+    Double(f64),
+    Char(char),
+    Array(Vec<Option<SharedValue>>),
+    Tuple(Vec<SharedValue>),
+}
+
+impl TryFrom<Immediate> for SharedValue {
+    type Error = Fault;
+
+    fn try_from(value: Immediate) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Immediate::U8(d) => SharedValue::U8(d),
+            Immediate::U16(d) => SharedValue::U16(d),
+            Immediate::U32(d) => SharedValue::U32(d),
+            Immediate::U64(d) => SharedValue::U64(d),
+            Immediate::USize(d) => SharedValue::USize(d),
+            Immediate::Float(d) => SharedValue::Float(d),
+            Immediate::Double(d) => SharedValue::Double(d),
+            Immediate::Char(d) => SharedValue::Char(d),
+            Immediate::Array(array) => {
+                let mut shared = Vec::with_capacity(array.len());
+                for element in array {
+                    shared.push(element.map(SharedValue::try_from).transpose()?);
+                }
+                SharedValue::Array(shared)
+            }
+            Immediate::Variant(Variant::Tuple(fields)) => SharedValue::Tuple(
+                fields
+                    .into_iter()
+                    .map(SharedValue::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(Fault::NotShareable),
+        })
+    }
+}
+
+impl From<SharedValue> for Immediate {
+    fn from(value: SharedValue) -> Self {
+        match value {
+            SharedValue::U8(d) => Immediate::U8(d),
+            SharedValue::U16(d) => Immediate::U16(d),
+            SharedValue::U32(d) => Immediate::U32(d),
+            SharedValue::U64(d) => Immediate::U64(d),
+            SharedValue::USize(d) => Immediate::USize(d),
+            SharedValue::Float(d) => Immediate::Float(d),
+            SharedValue::Double(d) => Immediate::Double(d),
+            SharedValue::Char(d) => Immediate::Char(d),
+            SharedValue::Array(array) => Immediate::Array(
+                array
+                    .into_iter()
+                    .map(|element| element.map(Immediate::from))
+                    .collect(),
+            ),
+            SharedValue::Tuple(fields) => Immediate::Variant(Variant::Tuple(
+                fields.into_iter().map(Immediate::from).collect(),
+            )),
+        }
+    }
+}
+
+/// Named values shared by every isolate that holds a clone of the heap. Every operation takes
+/// the lock for its whole duration, so each one is atomic.
+#[derive(Clone, Default)]
+pub struct SharedHeap {
+    values: Arc<RwLock<HashMap<String, SharedValue>>>,
+}
+
+impl SharedHeap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn store(&self, name: &str, value: SharedValue) {
+        let mut writer = self.values.write().expect("Shared heap poisoned");
+        writer.insert(name.to_string(), value);
+    }
+
+    pub fn load(&self, name: &str) -> Option<SharedValue> {
+        let reader = self.values.read().expect("Shared heap poisoned");
+        reader.get(name).cloned()
+    }
+
+    /// Stores `new` if the current value is `expected`. Gets the value from before the exchange,
+    /// and whether the exchange happened.
+    pub fn compare_and_swap(
+        &self,
+        name: &str,
+        expected: &SharedValue,
+        new: SharedValue,
+    ) -> (Option<SharedValue>, bool) {
+        let mut writer = self.values.write().expect("Shared heap poisoned");
+        let current = writer.get(name).cloned();
+        let swapped = current.as_ref() == Some(expected);
+        if swapped {
+            writer.insert(name.to_string(), new);
+        }
+        (current, swapped)
+    }
+}
+
+/// Instructions or type descriptors that were checked to not hold any raw pointers
+struct PointerFree<T>(T);
+
+// SAFETY: `Instruction` and `TypeDescriptor` are only kept from being `Send` and `Sync` by the raw
+// pointers an `Immediate` can hold, in `Pointer`, `PointerConst` and the environment of a
+// `Closure`. Everything else they hold is owned data, or reference counted through `std::sync`.
+// A `PointerFree` is only made by `Program::new`, after `shareable_instruction` and
+// `shareable_type` found none of those immediates, and it is never changed afterwards. The checks
+// match every variant and field without a wildcard, so anything added later that could hold an
+// immediate does not compile until it is checked too.
+unsafe impl Send for PointerFree<Vec<Instruction>> {}
+unsafe impl Sync for PointerFree<Vec<Instruction>> {}
+unsafe impl Send for PointerFree<Vec<Arc<TypeDescriptor>>> {}
+unsafe impl Sync for PointerFree<Vec<Arc<TypeDescriptor>>> {}
+
+/// Code and type descriptors that can be shared by isolates on different threads.
+///
+/// The instructions and types are checked when the program is created to make sure they do not
+/// hold any pointers, so the same program can be run by several isolates at once.
+#[derive(Clone)]
+pub struct Program {
+    instructions: Arc<PointerFree<Vec<Instruction>>>,
+    types: Arc<PointerFree<Vec<Arc<TypeDescriptor>>>>,
+}
+
+impl Program {
+    pub fn new(
+        instructions: Vec<Instruction>,
+        types: Vec<Arc<TypeDescriptor>>,
+    ) -> Result<Self, Fault> {
+        let shareable = instructions.iter().all(shareable_instruction)
+            && types.iter().all(|descriptor| shareable_type(descriptor));
+        if !shareable {
+            return Err(Fault::NotShareable);
+        }
+        Ok(Program {
+            instructions: Arc::new(PointerFree(instructions)),
+            types: Arc::new(PointerFree(types)),
+        })
+    }
+
+    pub(crate) fn get_instructions(&self) -> &[Instruction] {
+        &self.instructions.0
+    }
+
+    pub fn get_types(&self) -> &Vec<Arc<TypeDescriptor>> {
+        &self.types.0
+    }
+}
+
+/// Why an isolate did not finish
+#[derive(Debug)]
+pub enum IsolateError {
+    /// The program faulted, described by the fault
+    Faulted(String),
+    Panicked,
+}
+
+/// A virtual machine running a program on its own thread, with its own stacks, registers and
+/// memory. Isolates only exchange values through a [`SharedHeap`].
+pub struct Isolate {
+    handle: JoinHandle<Result<u32, IsolateError>>,
+}
+
+impl Isolate {
+    pub fn spawn(program: &Program, start: usize, heap: &SharedHeap) -> Self {
+        let program = program.clone();
+        let heap = heap.clone();
+        let handle = std::thread::spawn(move || {
+            let mut vm = VirtualMachine::new();
+            vm.set_shared_heap(heap);
+            vm.execute_program(&program, start)
+                .map_err(|fault| IsolateError::Faulted(fault.to_string()))
+        });
+        Isolate { handle }
+    }
+
+    /// Waits for the program to halt, and gets its exit code
+    pub fn join(self) -> Result<u32, IsolateError> {
+        self.handle.join().unwrap_or(Err(IsolateError::Panicked))
+    }
+}
+
+fn shareable_immediate(immediate: &Immediate) -> bool {
+    match immediate {
+        Immediate::U8(_)
+        | Immediate::U16(_)
+        | Immediate::U32(_)
+        | Immediate::U64(_)
+        | Immediate::USize(_)
+        | Immediate::Float(_)
+        | Immediate::Double(_)
+        | Immediate::Char(_) => true,
+        Immediate::Pointer(_) | Immediate::PointerConst(_) | Immediate::Closure(_) => false,
+        Immediate::DetailedType(_) => false,
+        Immediate::Array(array) => array.iter().flatten().all(shareable_immediate),
+        Immediate::Variant(variant) => shareable_variant(variant),
+        Immediate::Function(function) => shareable_function(function),
+    }
+}
+
+fn shareable_variant(variant: &Variant) -> bool {
+    match variant {
+        Variant::Tuple(fields) => fields.iter().all(shareable_immediate),
+        Variant::Structure { fields, .. } => fields.values().all(shareable_immediate),
+        Variant::Empty => true,
+    }
+}
+
+// `Option::is_none_or` is newer than the toolchains this crate still builds on
+#[allow(clippy::unnecessary_map_or)]
+fn shareable_function(function: &Function) -> bool {
+    function
+        .get_parameters()
+        .iter()
+        .all(|(_, parameter)| shareable_immediate(parameter))
+        && function.get_ret_type().map_or(true, shareable_immediate)
+        && function
+            .get_instructions()
+            .iter()
+            .all(shareable_instruction)
+}
+
+fn shareable_literal(literal: &Literal) -> bool {
+    match literal {
+        Literal::Immediate(immediate) => shareable_immediate(immediate),
+        Literal::Variable(_) | Literal::Register(..) | Literal::Peak | Literal::Argument(_) => true,
+    }
+}
+
+fn shareable_instruction(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::PushVal(immediate)
+        | Instruction::Throw(immediate)
+        | Instruction::Coerce {
+            dest_type: immediate,
+        }
+        | Instruction::Convert {
+            dest_type: immediate,
+            ..
+        } => shareable_immediate(immediate),
+        Instruction::PopTo(literal)
+        | Instruction::AddressOf(literal)
+        | Instruction::Push { src: literal }
+        | Instruction::GetField(literal, _)
+        | Instruction::GetMember(literal, _) => shareable_literal(literal),
+        Instruction::Ret(literal) => literal.iter().all(shareable_literal),
+        Instruction::Move { dest, src } => shareable_literal(dest) && shareable_literal(src),
+        Instruction::CallFunction(function)
+        | Instruction::TailCallFunction(function)
+        | Instruction::MakeClosure { function, .. } => shareable_function(function),
+        Instruction::BuildVariant { dest_variant } => shareable_variant(dest_variant),
+        Instruction::Pop
+        | Instruction::Jump(_)
+        | Instruction::Compare(_)
+        | Instruction::PerformOperation(..)
+        | Instruction::PerformUnaryOperation(_)
+        | Instruction::FusedMultiplyAdd
+        | Instruction::ConditionalJump(..)
+        | Instruction::Dereference
+        | Instruction::Call(_)
+        | Instruction::Catch
+        | Instruction::Nop
+        | Instruction::Halt
+        | Instruction::DeclareVar(..)
+        | Instruction::GetVar(_)
+        | Instruction::SaveVar(_)
+        | Instruction::Enter
+        | Instruction::Lower
+        | Instruction::Exit
+        | Instruction::Heapify
+        | Instruction::CallIndirect
+        | Instruction::JumpIndirect
+        | Instruction::TailCall(_)
+        | Instruction::Spawn(_)
+        | Instruction::Yield
+        | Instruction::Join
+        | Instruction::MakeChannel
+        | Instruction::Send
+        | Instruction::Receive
+        | Instruction::SharedStore(_)
+        | Instruction::SharedLoad(_)
+        | Instruction::SharedCompareSwap(_)
+        | Instruction::CallSymbol(_)
+        | Instruction::EnableInterrupts
+        | Instruction::DisableInterrupts
+        | Instruction::ReturnFromInterrupt => true,
+    }
+}
+
+fn shareable_type(descriptor: &TypeDescriptor) -> bool {
+    let TypeDescriptor {
+        identifier: _,
+        is_trait: _,
+        is_struct: _,
+        is_enum: _,
+        is_call: _,
+        v_tables,
+        parents,
+        parent_data,
+        variants,
+    } = descriptor;
+    let variants = match variants {
+        StorageType::Variants(variants) => variants.values().all(shareable_variant),
+        StorageType::Single(variant) => shareable_variant(variant),
+        StorageType::None => true,
+    };
+    variants
+        && parent_data.values().all(shareable_variant)
+        && v_tables
+            .iter()
+            .flat_map(|table| table.values())
+            .flatten()
+            .all(shareable_function)
+        && parents
+            .iter()
+            .filter_map(|parent| parent.upgrade())
+            .all(|parent| shareable_type(&parent))
+}
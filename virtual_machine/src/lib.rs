@@ -3,12 +3,19 @@
 #[macro_use]
 extern crate lazy_static;
 
-mod flags;
+pub mod analysis;
+pub mod calling_convention;
+pub mod flags;
 pub mod instruction_set;
+pub mod interrupts;
 pub mod intrinsics;
+pub mod isolate;
+pub mod linking;
 pub mod memory;
+pub mod optimization;
 pub mod registers;
 pub mod resolution;
+pub mod scheduler;
 pub mod vm;
 
 pub use vm::VirtualMachine;
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+
+use crate::instruction_set::{Immediate, Instruction};
+use crate::resolution::{FullIdentifier, Identifier};
+
+/// A problem found while loading or linking a [`Module`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkError {
+    /// A `CallSymbol` names a function that no loaded module exports
+    UnresolvedSymbol(FullIdentifier),
+    /// Two loaded modules export the same function or global
+    DuplicateSymbol(FullIdentifier),
+    /// A jump, call or export of a module targets an address outside of its instructions
+    InvalidAddress {
+        module: FullIdentifier,
+        address: usize,
+    },
+}
+
+/// An independently compiled unit of code.
+///
+/// Every address in a module is relative to its first instruction, and is relocated when the
+/// module is loaded into a virtual machine. Addresses stored as data are not relocated, like in
+/// `Function`s. The exports of a module are named within its namespace, so `add` exported by
+/// `math` is called with `CallSymbol(math::add)`, and a global `pi` is the static variable
+/// `math::pi`.
+#[derive(Debug, Clone)]
+pub struct Module {
+    namespace: FullIdentifier,
+    instructions: Vec<Instruction>,
+    functions: Vec<(Identifier, usize)>,
+    globals: Vec<(Identifier, Option<Immediate>)>,
+}
+
+impl Module {
+    pub fn get_namespace(&self) -> &FullIdentifier {
+        &self.namespace
+    }
+
+    pub fn get_instructions(&self) -> &Vec<Instruction> {
+        &self.instructions
+    }
+
+    /// The full name of something this module exports
+    pub fn symbol(&self, name: &Identifier) -> FullIdentifier {
+        FullIdentifier::from_iter((&self.namespace).into_iter().chain(Some(name)).cloned())
+    }
+
+    /// The exported functions, with their full names and addresses relative to the module
+    pub fn functions(&self) -> impl Iterator<Item = (FullIdentifier, usize)> + '_ {
+        self.functions
+            .iter()
+            .map(move |(name, address)| (self.symbol(name), *address))
+    }
+
+    /// The exported globals, with their full names and initial values
+    pub fn globals(&self) -> impl Iterator<Item = (FullIdentifier, Option<&Immediate>)> + '_ {
+        self.globals
+            .iter()
+            .map(move |(name, value)| (self.symbol(name), value.as_ref()))
+    }
+
+    /// Checks that every address in the module is within it
+    pub fn validate(&self) -> Result<(), LinkError> {
+        let targets = self
+            .instructions
+            .iter()
+            .filter_map(Instruction::jump_target)
+            .chain(self.functions.iter().map(|(_, address)| *address));
+        for address in targets {
+            if address >= self.instructions.len() {
+                return Err(LinkError::InvalidAddress {
+                    module: self.namespace.clone(),
+                    address,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct ModuleBuilder {
+    in_progress: Module,
+}
+
+impl ModuleBuilder {
+    pub fn with_namespace(namespace: FullIdentifier) -> Self {
+        ModuleBuilder {
+            in_progress: Module {
+                namespace,
+                instructions: vec![],
+                functions: vec![],
+                globals: vec![],
+            },
+        }
+    }
+
+    pub fn with_instructions(mut self, instructions: Vec<Instruction>) -> Self {
+        self.in_progress.instructions = instructions;
+        self
+    }
+
+    /// Exports the function starting at `address`
+    pub fn with_function<I: Into<Identifier>>(mut self, name: I, address: usize) -> Self {
+        self.in_progress.functions.push((name.into(), address));
+        self
+    }
+
+    /// Exports a global, which is declared when the module is loaded
+    pub fn with_global<I: Into<Identifier>>(mut self, name: I, value: Option<Immediate>) -> Self {
+        self.in_progress.globals.push((name.into(), value));
+        self
+    }
+
+    pub fn build(self) -> Module {
+        self.in_progress
+    }
+}
+
+/// Every symbol exported by the modules loaded into a virtual machine
+#[derive(Default)]
+pub(crate) struct SymbolTable {
+    functions: HashMap<FullIdentifier, usize>,
+    globals: HashSet<FullIdentifier>,
+}
+
+impl SymbolTable {
+    pub(crate) fn clear(&mut self) {
+        self.functions.clear();
+        self.globals.clear();
+    }
+
+    pub(crate) fn function(&self, symbol: &FullIdentifier) -> Option<usize> {
+        self.functions.get(symbol).copied()
+    }
+
+    /// Adds the exports of a module that is loaded at `base`, without adding any of them if one
+    /// is already taken
+    pub(crate) fn add(&mut self, module: &Module, base: usize) -> Result<(), LinkError> {
+        let functions: Vec<_> = module.functions().collect();
+        let globals: Vec<_> = module.globals().map(|(symbol, _)| symbol).collect();
+        let symbols = functions.iter().map(|(symbol, _)| symbol).chain(&globals);
+        for symbol in symbols {
+            if self.functions.contains_key(symbol) || self.globals.contains(symbol) {
+                return Err(LinkError::DuplicateSymbol(symbol.clone()));
+            }
+        }
+        for (symbol, address) in functions {
+            self.functions.insert(symbol, base + address);
+        }
+        for symbol in globals {
+            self.globals.insert(symbol);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::iter::FromIterator;
+
+    use crate::identifier;
+    use crate::instruction_set::Instruction::{Halt, Jump, Nop};
+    use crate::linking::{LinkError, ModuleBuilder, SymbolTable};
+    use crate::resolution::FullIdentifier;
+
+    #[test]
+    fn exports_are_namespaced() {
+        let module = ModuleBuilder::with_namespace(identifier!(std::math))
+            .with_instructions(vec![Nop, Halt])
+            .with_function("add", 1)
+            .build();
+        let functions: Vec<_> = module.functions().collect();
+        assert_eq!(functions, vec![(identifier!(std::math::add), 1)]);
+
+        let mut symbols = SymbolTable::default();
+        symbols.add(&module, 10).unwrap();
+        assert_eq!(symbols.function(&identifier!(std::math::add)), Some(11));
+        assert_eq!(
+            symbols.add(&module, 20),
+            Err(LinkError::DuplicateSymbol(identifier!(std::math::add)))
+        );
+    }
+
+    #[test]
+    fn addresses_stay_in_module() {
+        let module = ModuleBuilder::with_namespace(FullIdentifier::from("bad"))
+            .with_instructions(vec![Jump(2), Halt])
+            .build();
+        assert_eq!(
+            module.validate(),
+            Err(LinkError::InvalidAddress {
+                module: FullIdentifier::from("bad"),
+                address: 2
+            })
+        );
+    }
+}
@@ -20,12 +20,15 @@ struct Variables
 // A variable can exist for shorter than it's value, but it should not exist for longer than it's value
 {
     mapping: HashMap<String, usize>,
+    /// Variables captured by a closure, which live on the heap instead of in memory
+    captured: HashMap<String, *mut Immediate>,
 }
 
 impl Variables {
     fn new() -> Self {
         Self {
             mapping: HashMap::new(),
+            captured: HashMap::new(),
         }
     }
 }
@@ -50,6 +53,14 @@ impl Memory {
         }
     }
 
+    /// Creates the memory of a new execution context, which only shares static memory with this
+    pub(crate) fn new_sharing_statics(&self) -> Self {
+        Self {
+            static_memory: self.static_memory.clone(),
+            ..Memory::new()
+        }
+    }
+
     fn get_scope(&self) -> &Variables {
         self.local_scope_stack.last().unwrap()
     }
@@ -68,6 +79,54 @@ impl Memory {
         self.local_scope_stack.push(new_scope);
     }
 
+    /// Enters a new local scope where the captured variables of a closure are visible
+    pub fn new_closure_scope(&mut self, environment: &[(String, *mut Immediate)]) {
+        let mut new_scope = Variables::new();
+        new_scope.captured = environment.iter().cloned().collect();
+        self.local_scope_stack.push(new_scope);
+    }
+
+    fn get_captured(&self, name: &String) -> Option<*mut Immediate> {
+        if self.get_scope().mapping.contains_key(name) {
+            return None;
+        }
+        self.get_scope().captured.get(name).copied()
+    }
+
+    /// Moves a local variable onto the heap so it can outlive its scope. Every scope that sees the
+    /// variable is bound to the heap value instead, so writes through a closure and writes in the
+    /// defining scope go to the same place, and capturing it again gives the same pointer.
+    pub fn capture_variable(&mut self, name: &String) -> Result<*mut Immediate, Fault> {
+        if let Some(ptr) = self.get_captured(name) {
+            return Ok(ptr);
+        }
+        let pos = match self.get_scope().mapping.get(name) {
+            None => return Err(NotAVariable(name.to_string())),
+            Some(pos) => *pos,
+        };
+        let value = self.memory[pos].clone().ok_or(SegmentationFault)?;
+        let ptr = self.heapify(value);
+        for vars in &mut self.local_scope_stack {
+            let captured = &mut vars.captured;
+            vars.mapping.retain(|other_name, other| {
+                if *other != pos {
+                    return true;
+                }
+                captured.insert(other_name.clone(), ptr);
+                false
+            });
+        }
+        // No scope maps a variable to the slot anymore, so it can be reused
+        self.memory[pos] = None;
+        self.free_list.push(pos);
+        Ok(ptr)
+    }
+
+    /// How many local scopes have been entered
+    pub(crate) fn scope_depth(&self) -> usize {
+        self.local_scope_stack.len()
+    }
+
     pub fn exit_local_scope(&mut self) {
         self.local_scope_stack.pop().unwrap();
     }
@@ -100,6 +159,10 @@ impl Memory {
     }
 
     pub fn set_variable(&mut self, name: &String, value: Immediate) -> Result<(), Fault> {
+        if let Some(ptr) = self.get_captured(name) {
+            unsafe { *ptr = value };
+            return Ok(());
+        }
         if !self.get_scope().mapping.contains_key(name) {
             let mut writer = self.static_memory.write().expect("Statics poisoned");
             match writer.get_mut(name) {
@@ -122,6 +185,9 @@ impl Memory {
     }
 
     pub fn get_variable(&self, name: &String) -> Result<Immediate, Fault> {
+        if let Some(ptr) = self.get_captured(name) {
+            return Ok(unsafe { (*ptr).clone() });
+        }
         if !self.get_scope().mapping.contains_key(name) {
             let mut reader = self.static_memory.read().expect("Statics poisoned");
             match reader.get(name) {
@@ -152,6 +218,9 @@ impl Memory {
         }
     }
     pub fn get_variable_ref(&self, name: &String) -> Result<&Immediate, Fault> {
+        if let Some(ptr) = self.get_captured(name) {
+            return Ok(unsafe { &*ptr });
+        }
         if !self.get_scope().mapping.contains_key(name) {
             let mut reader = self.static_memory.read().expect("Statics poisoned");
             match reader.get(name) {
@@ -183,6 +252,9 @@ impl Memory {
     }
 
     pub fn get_variable_mut(&mut self, name: &String) -> Result<&mut Immediate, Fault> {
+        if let Some(ptr) = self.get_captured(name) {
+            return Ok(unsafe { &mut *ptr });
+        }
         if !self.get_scope().mapping.contains_key(name) {
             let mut writer = self.static_memory.write().expect("Statics poisoned");
             match writer.get_mut(name) {
@@ -239,3 +311,41 @@ impl Memory {
         self.free_list = unused;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::instruction_set::Immediate;
+    use crate::memory::{Memory, Scope};
+
+    #[test]
+    fn captured_slots_are_reused() {
+        let mut memory = Memory::new();
+        let x = "x".to_string();
+        memory.declare_variable(&x, &Scope::Local);
+        memory.set_variable(&x, Immediate::U32(3)).unwrap();
+        let ptr = memory.capture_variable(&x).unwrap();
+        assert_eq!(unsafe { (*ptr).clone() }, Immediate::U32(3));
+        assert_eq!(memory.get_variable(&x).unwrap(), Immediate::U32(3));
+
+        memory.declare_variable(&"y".to_string(), &Scope::Local);
+        assert_eq!(memory.memory.len(), 1);
+    }
+
+    #[test]
+    fn enclosing_scopes_share_captures() {
+        let mut memory = Memory::new();
+        let x = "x".to_string();
+        memory.declare_variable(&x, &Scope::Local);
+        memory.set_variable(&x, Immediate::U32(3)).unwrap();
+        memory.new_lower_scope();
+        let ptr = memory.capture_variable(&x).unwrap();
+        memory.exit_local_scope();
+
+        unsafe { *ptr = Immediate::U32(4) };
+        assert_eq!(memory.get_variable(&x).unwrap(), Immediate::U32(4));
+        memory.set_variable(&x, Immediate::U32(5)).unwrap();
+        assert_eq!(unsafe { (*ptr).clone() }, Immediate::U32(5));
+        assert_eq!(memory.capture_variable(&x).unwrap(), ptr);
+        assert_eq!(memory.free_list, vec![0]);
+    }
+}
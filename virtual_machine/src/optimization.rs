@@ -0,0 +1,72 @@
+use crate::instruction_set::Instruction;
+
+pub mod peephole;
+
+/// Maps instruction addresses of a program from before a pass to after it
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    mapping: Vec<usize>,
+    length: usize,
+}
+
+impl Relocation {
+    pub fn identity(length: usize) -> Self {
+        Relocation {
+            mapping: (0..length).collect(),
+            length,
+        }
+    }
+
+    /// Creates the relocation for removing every instruction where `keep` is false.
+    ///
+    /// Removed addresses are mapped to the next kept instruction, so a jump into a removed
+    /// instruction lands where execution would have continued.
+    pub fn from_kept(keep: &[bool]) -> Self {
+        let mut next = 0;
+        let mut mapping = Vec::with_capacity(keep.len());
+        for kept in keep {
+            mapping.push(next);
+            if *kept {
+                next += 1;
+            }
+        }
+        Relocation {
+            mapping,
+            length: next,
+        }
+    }
+
+    /// Gets the new address of `address`. Addresses past the end of the original program stay
+    /// past the end of the new one.
+    pub fn relocate(&self, address: usize) -> usize {
+        match self.mapping.get(address) {
+            Some(new) => *new,
+            None => self.length + (address - self.mapping.len()),
+        }
+    }
+
+    /// Applies `next` after this relocation
+    pub fn then(&self, next: &Relocation) -> Relocation {
+        Relocation {
+            mapping: self.mapping.iter().map(|pos| next.relocate(*pos)).collect(),
+            length: next.length,
+        }
+    }
+}
+
+/// Removes every instruction where `keep` is false, rewriting every absolute target to match.
+pub fn compact(instructions: Vec<Instruction>, keep: &[bool]) -> (Vec<Instruction>, Relocation) {
+    let relocation = Relocation::from_kept(keep);
+    let output = instructions
+        .into_iter()
+        .zip(keep.iter())
+        .filter(|(_, kept)| **kept)
+        .map(|(mut instruction, _)| {
+            if let Some(target) = instruction.jump_target_mut() {
+                *target = relocation.relocate(*target);
+            }
+            instruction
+        })
+        .collect();
+    (output, relocation)
+}
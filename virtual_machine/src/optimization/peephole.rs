@@ -0,0 +1,375 @@
+use std::collections::HashSet;
+
+use crate::flags::Flags;
+use crate::instruction_set::{Instruction, Literal};
+use crate::optimization::{compact, Relocation};
+
+/// Rewrites short instruction sequences into cheaper equivalents.
+///
+/// Every absolute `Jump`, `ConditionalJump`, `Call` and `TailCall` target is relocated as
+/// instructions are removed. Addresses stored as data are not known to the optimizer, so every address that is
+/// entered from outside of the instructions (such as the start address) must be registered as an
+/// entry point, and translated afterwards with [`Optimized::relocate`].
+pub struct PeepholeOptimizer {
+    entry_points: Vec<usize>,
+}
+
+/// The output of a [`PeepholeOptimizer`]
+pub struct Optimized {
+    instructions: Vec<Instruction>,
+    relocation: Relocation,
+}
+
+impl Optimized {
+    pub fn instructions(&self) -> &Vec<Instruction> {
+        &self.instructions
+    }
+
+    pub fn into_instructions(self) -> Vec<Instruction> {
+        self.instructions
+    }
+
+    /// Gets the address an instruction from the original program was moved to
+    pub fn relocate(&self, address: usize) -> usize {
+        self.relocation.relocate(address)
+    }
+}
+
+impl PeepholeOptimizer {
+    pub fn new(start: usize) -> Self {
+        PeepholeOptimizer {
+            entry_points: vec![start],
+        }
+    }
+
+    pub fn with_entry_point(mut self, entry: usize) -> Self {
+        self.entry_points.push(entry);
+        self
+    }
+
+    /// Runs every pass until none of them can make any more changes.
+    ///
+    /// Programs that handle code addresses as values are returned unchanged.
+    pub fn optimize(&self, instructions: Vec<Instruction>) -> Optimized {
+        let mut instructions = instructions;
+        let mut relocation = Relocation::identity(instructions.len());
+        if instructions.iter().any(Instruction::uses_code_addresses) {
+            return Optimized {
+                instructions,
+                relocation,
+            };
+        }
+        loop {
+            let entry_points: Vec<usize> = self
+                .entry_points
+                .iter()
+                .map(|entry| relocation.relocate(*entry))
+                .collect();
+
+            let mut changed = thread_jumps(&mut instructions);
+            let targets = targets(&instructions, &entry_points);
+            changed |= fold_operations(&mut instructions, &targets);
+            changed |= cancel_push_pop(&mut instructions, &targets);
+            changed |= remove_unreachable(&mut instructions, &entry_points);
+
+            let keep: Vec<bool> = instructions
+                .iter()
+                .map(|instruction| !matches!(instruction, Instruction::Nop))
+                .collect();
+            if keep.iter().any(|kept| !kept) {
+                let (compacted, pass_relocation) = compact(instructions, &keep);
+                instructions = compacted;
+                relocation = relocation.then(&pass_relocation);
+                changed = true;
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        Optimized {
+            instructions,
+            relocation,
+        }
+    }
+}
+
+/// Every address that control can arrive at other than by falling through
+fn targets(instructions: &[Instruction], entry_points: &[usize]) -> HashSet<usize> {
+    instructions
+        .iter()
+        .filter_map(Instruction::jump_target)
+        .chain(entry_points.iter().copied())
+        .collect()
+}
+
+/// Points jumps that land on an unconditional `Jump` at its final destination instead, and
+/// removes jumps to the instruction that would have run next anyway
+fn thread_jumps(instructions: &mut [Instruction]) -> bool {
+    let mut changed = false;
+    for index in 0..instructions.len() {
+        if let Instruction::Jump(target) = instructions[index] {
+            if target == index + 1 {
+                instructions[index] = Instruction::Nop;
+                changed = true;
+                continue;
+            }
+        }
+
+        let start = match instructions[index].jump_target() {
+            Some(target) => target,
+            None => continue,
+        };
+
+        let mut visited = HashSet::new();
+        let mut destination = start;
+        while let Some(Instruction::Jump(next)) = instructions.get(destination) {
+            if !visited.insert(destination) {
+                break;
+            }
+            destination = *next;
+        }
+
+        if destination != start {
+            *instructions[index].jump_target_mut().unwrap() = destination;
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Replaces `PushVal`, `PushVal`, `PerformOperation` with the `PushVal` of the result.
+///
+/// This is only done when nothing can observe the flags the operation would have set.
+fn fold_operations(instructions: &mut [Instruction], targets: &HashSet<usize>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 2 < instructions.len() {
+        if targets.contains(&(index + 1)) || targets.contains(&(index + 2)) {
+            index += 1;
+            continue;
+        }
+
+        let folded = match &instructions[index..index + 3] {
+            [Instruction::PushVal(below), Instruction::PushVal(top), Instruction::PerformOperation(operation, mode)] => {
+                if flags_overwritten(instructions, index + 3) {
+                    operation
+                        .perform_op(*mode, &mut Flags::new(), top.clone(), below.clone())
+                        .ok()
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        match folded {
+            Some(result) => {
+                instructions[index] = Instruction::PushVal(result);
+                instructions[index + 1] = Instruction::Nop;
+                instructions[index + 2] = Instruction::Nop;
+                changed = true;
+                index += 3;
+            }
+            None => index += 1,
+        }
+    }
+    changed
+}
+
+/// Whether the flags are set again before anything could read them, starting at `index`
+fn flags_overwritten(instructions: &[Instruction], index: usize) -> bool {
+    for instruction in &instructions[index..] {
+        if instruction.reads_flags() {
+            return false;
+        }
+        match instruction {
+            Instruction::PerformOperation(..)
+            | Instruction::PerformUnaryOperation(_)
+            | Instruction::FusedMultiplyAdd
+            | Instruction::Compare(_)
+            | Instruction::Halt => return true,
+            _ if instruction.jump_target().is_some()
+                || instruction.is_call()
+                || !instruction.falls_through() =>
+            {
+                return false
+            }
+            _ => {}
+        }
+    }
+    true
+}
+
+/// Removes values that are pushed and then immediately discarded. Only pushes of immediates are
+/// removed, as reading a variable or register can fault.
+fn cancel_push_pop(instructions: &mut [Instruction], targets: &HashSet<usize>) -> bool {
+    let mut changed = false;
+    let mut index = 0;
+    while index + 1 < instructions.len() {
+        let cancels = !targets.contains(&(index + 1))
+            && matches!(
+                &instructions[index..index + 2],
+                [
+                    Instruction::Push {
+                        src: Literal::Immediate(_)
+                    },
+                    Instruction::Pop
+                ] | [Instruction::PushVal(_), Instruction::Pop]
+            );
+
+        if cancels {
+            instructions[index] = Instruction::Nop;
+            instructions[index + 1] = Instruction::Nop;
+            changed = true;
+            index += 2;
+        } else {
+            index += 1;
+        }
+    }
+    changed
+}
+
+/// Replaces every instruction control can never reach from an entry point with a `Nop`
+fn remove_unreachable(instructions: &mut [Instruction], entry_points: &[usize]) -> bool {
+    let mut reachable = vec![false; instructions.len()];
+    let mut work_list: Vec<usize> = entry_points.to_vec();
+    while let Some(index) = work_list.pop() {
+        let instruction = match instructions.get(index) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+
+        if let Some(target) = instruction.jump_target() {
+            work_list.push(target);
+        }
+        if instruction.falls_through() {
+            work_list.push(index + 1);
+        }
+    }
+
+    let mut changed = false;
+    for (instruction, reachable) in instructions.iter_mut().zip(reachable) {
+        if !reachable && !matches!(instruction, Instruction::Nop) {
+            *instruction = Instruction::Nop;
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use crate::instruction_set::Immediate::{USize, U32};
+    use crate::instruction_set::Instruction::*;
+    use crate::instruction_set::{ArithmeticMode, JumpType, Literal, Operation, RegisterType};
+    use crate::optimization::peephole::PeepholeOptimizer;
+    use crate::vm::VirtualMachine;
+
+    #[test]
+    fn removes_nops_and_relocates() {
+        let instructions = vec![Nop, Jump(3), Nop, Nop, PushVal(U32(0)), Halt];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        assert_eq!(optimized.instructions().len(), 2);
+        assert_eq!(optimized.relocate(0), 0);
+        assert_eq!(optimized.relocate(4), 0);
+        assert!(matches!(optimized.instructions()[0], PushVal(U32(0))));
+    }
+
+    #[test]
+    fn folds_constant_operations() {
+        let instructions = vec![
+            PushVal(U32(2)),
+            PushVal(U32(7)),
+            PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+            Halt,
+        ];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        assert_eq!(optimized.instructions().len(), 2);
+        assert!(matches!(optimized.instructions()[0], PushVal(U32(5))));
+    }
+
+    #[test]
+    fn keeps_operations_whose_flags_are_read() {
+        let instructions = vec![
+            PushVal(U32(2)),
+            PushVal(U32(7)),
+            PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+            ConditionalJump(JumpType::Zero, 5),
+            Halt,
+            Halt,
+        ];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        assert!(matches!(
+            optimized.instructions()[2],
+            PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping)
+        ));
+    }
+
+    #[test]
+    fn cancels_push_pop() {
+        let instructions = vec![
+            Push {
+                src: Literal::Immediate(U32(1)),
+            },
+            Pop,
+            PushVal(U32(0)),
+            Pop,
+            PushVal(U32(0)),
+            Halt,
+        ];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        assert_eq!(optimized.instructions().len(), 2);
+    }
+
+    #[test]
+    fn keeps_push_pop_that_can_fault() {
+        let instructions = vec![
+            Push {
+                src: Literal::Variable("unbound".to_string()),
+            },
+            Pop,
+            Push {
+                src: Literal::Register(RegisterType::Caller, 0),
+            },
+            Pop,
+            PushVal(U32(0)),
+            Halt,
+        ];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        assert_eq!(optimized.instructions().len(), 6);
+        let result = VirtualMachine::new().execute(optimized.into_instructions(), 0);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn threads_jumps() {
+        let instructions = vec![Call(3), Halt, Nop, Jump(4), PushVal(USize(1)), Ret(None)];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        let instructions = optimized.instructions();
+        assert_eq!(instructions.len(), 4);
+        assert!(matches!(instructions[0], Call(2)));
+        assert!(matches!(instructions[2], PushVal(USize(1))));
+    }
+
+    #[test]
+    fn removes_unreachable_code() {
+        let instructions = vec![
+            Jump(3),
+            PushVal(U32(1)),
+            Halt,
+            PushVal(U32(2)),
+            Halt,
+            PushVal(U32(3)),
+        ];
+        let optimized = PeepholeOptimizer::new(0).optimize(instructions);
+        let instructions = optimized.instructions();
+        assert_eq!(instructions.len(), 2);
+        assert!(matches!(instructions[0], PushVal(U32(2))));
+    }
+}
@@ -1,4 +1,7 @@
+use std::convert::TryFrom;
+
 use crate::instruction_set::Immediate;
+use crate::vm::Fault;
 
 pub const REGISTER_COUNT: usize = 8;
 
@@ -33,3 +36,38 @@ impl Registers {
         }
     }
 }
+
+/// The registers of the `Special` class, which expose the state of the virtual machine
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SpecialRegister {
+    /// The address of the running instruction. Writing to it jumps to the written address.
+    ProgramCounter = 0,
+    /// The depth of the value stack. Writing a smaller depth discards values off the top.
+    StackPointer = 1,
+    /// The stack depth the innermost call was made at, under any calling convention, or 0 outside
+    /// of a call. Read only.
+    FramePointer = 2,
+    /// The flags, packed into a `U16` word
+    Flags = 3,
+}
+
+impl SpecialRegister {
+    pub fn is_writable(&self) -> bool {
+        !matches!(self, SpecialRegister::FramePointer)
+    }
+}
+
+impl TryFrom<u8> for SpecialRegister {
+    type Error = Fault;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SpecialRegister::ProgramCounter),
+            1 => Ok(SpecialRegister::StackPointer),
+            2 => Ok(SpecialRegister::FramePointer),
+            3 => Ok(SpecialRegister::Flags),
+            _ => Err(Fault::InvalidRegister),
+        }
+    }
+}
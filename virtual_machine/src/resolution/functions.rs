@@ -26,6 +26,33 @@ impl Function {
     }
 }
 
+/// A function paired with the variables it captured when it was created.
+///
+/// Captured values are moved onto the heap, so they outlive the scope they were captured from and
+/// are shared by every copy of the closure.
+#[derive(Clone, Debug)]
+pub struct Closure {
+    function: Function,
+    environment: Vec<(String, *mut Immediate)>,
+}
+
+impl Closure {
+    pub fn new(function: Function, environment: Vec<(String, *mut Immediate)>) -> Self {
+        Closure {
+            function,
+            environment,
+        }
+    }
+
+    pub fn get_function(&self) -> &Function {
+        &self.function
+    }
+
+    pub fn get_environment(&self) -> &Vec<(String, *mut Immediate)> {
+        &self.environment
+    }
+}
+
 pub struct FunctionBuilder {
     in_progress: Function,
     set_parameters: bool,
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::{Weak, Arc};
 
+use crate::calling_convention::identical_variants;
 use crate::instruction_set::Immediate;
 use crate::intrinsics::simplification::{Simplifier, TupleMember};
 use crate::resolution::types::descriptor::{TypeDescriptor, Variant};
@@ -45,6 +46,17 @@ impl TypedObject {
         self.parent_variants.get_mut(parent).unwrap()
     }
 
+    /// Whether two objects are of the same type and hold exactly the same values
+    pub(crate) fn identical(&self, other: &TypedObject) -> bool {
+        self.descriptor.ptr_eq(&other.descriptor)
+            && identical_variants(&self.self_variant, &other.self_variant)
+            && self.parent_variants.len() == other.parent_variants.len()
+            && self.parent_variants.iter().all(|(parent, variant)| {
+                let other = other.parent_variants.get(parent);
+                matches!(other, Some(other) if identical_variants(variant, other))
+            })
+    }
+
     pub fn get_descriptor(&self) -> Arc<TypeDescriptor> {
         self.descriptor.upgrade().unwrap()
     }
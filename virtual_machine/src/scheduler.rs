@@ -0,0 +1,59 @@
+use std::collections::VecDeque;
+
+use crate::calling_convention::CallFrame;
+use crate::flags::Flags;
+use crate::instruction_set::Immediate;
+use crate::interrupts::InterruptFrame;
+use crate::memory::Memory;
+use crate::registers::Registers;
+
+/// The id of the context that `execute` starts in
+pub const MAIN_CONTEXT: usize = 0;
+
+/// The state of a green thread that is not currently running.
+///
+/// The running context lives in the fields of the virtual machine, and is swapped with its
+/// entry here whenever the scheduler switches contexts.
+pub(crate) struct ExecutionContext {
+    pub(crate) program_counter: usize,
+    pub(crate) stack: Vec<Immediate>,
+    pub(crate) registers: Registers,
+    pub(crate) flags: Flags,
+    pub(crate) frames: Vec<CallFrame>,
+    pub(crate) memory: Memory,
+    pub(crate) interrupt_frames: Vec<InterruptFrame>,
+    pub(crate) state: ContextState,
+}
+
+impl ExecutionContext {
+    pub(crate) fn new(program_counter: usize, registers: Registers, memory: Memory) -> Self {
+        ExecutionContext {
+            program_counter,
+            stack: vec![],
+            registers,
+            flags: Flags::new(),
+            frames: vec![],
+            memory,
+            interrupt_frames: vec![],
+            state: ContextState::Ready,
+        }
+    }
+}
+
+pub(crate) enum ContextState {
+    Ready,
+    /// The context halted, leaving this value on top of its stack
+    Finished(Immediate),
+}
+
+/// Why the running context gave up control
+pub(crate) enum Schedule {
+    /// The context can keep running once the others have had a turn
+    Yield,
+    /// The context is waiting on another context, and retries the same instruction when it is
+    /// next scheduled
+    Block,
+}
+
+/// An unbounded queue of values sent between contexts
+pub(crate) type Channel = VecDeque<Immediate>;
@@ -1,25 +1,77 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
+use crate::calling_convention::{identical, CallFrame, CallingConvention, RETURN_REGISTER};
 use crate::flags::Flags;
-use crate::instruction_set::Immediate::{Double, Float, U16, U32, U64, U8};
-use crate::instruction_set::{Immediate, Instruction, JumpType, Literal, RegisterType};
-use crate::memory::Memory;
-use crate::registers::Registers;
+use crate::instruction_set::Immediate::U32;
+use crate::instruction_set::arithmetic::fused_multiply_add;
+use crate::instruction_set::{ConversionKind, Immediate, Instruction, Literal, RegisterType};
+use crate::interrupts::{InterruptFrame, InterruptHandle, Timer, TIMER_INTERRUPT, TRAP_INTERRUPT};
+use crate::isolate::{Program, SharedHeap, SharedValue};
+use crate::linking::{LinkError, Module, SymbolTable};
+use crate::memory::{Memory, Scope};
+use crate::registers::{Registers, SpecialRegister};
+use crate::resolution::functions::{Closure, Function};
 use crate::resolution::types::descriptor::Variant;
-use crate::resolution::{Identifier, FullIdentifier};
+use crate::resolution::{FullIdentifier, Identifier, Resolvable};
+use crate::scheduler::{Channel, ContextState, ExecutionContext, Schedule, MAIN_CONTEXT};
 use crate::vm::Fault::{PrimitiveTypeMismatch, SegmentationFault};
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::Deref;
 use crate::intrinsics::simplification::{TupleMember, Simplifier};
 
+/// The instructions of a virtual machine, which are shared with a [`Program`] until they change
+enum Code {
+    Owned(Vec<Instruction>),
+    Shared(Program),
+}
+
+impl Code {
+    /// Gets the instructions to change, copying them out of the program they are shared with
+    fn to_mut(&mut self) -> &mut Vec<Instruction> {
+        if let Code::Shared(program) = self {
+            *self = Code::Owned(program.get_instructions().to_vec());
+        }
+        match self {
+            Code::Owned(instructions) => instructions,
+            Code::Shared(_) => unreachable!("Shared code was just copied"),
+        }
+    }
+}
+
+impl Deref for Code {
+    type Target = [Instruction];
+
+    fn deref(&self) -> &[Instruction] {
+        match self {
+            Code::Owned(instructions) => instructions,
+            Code::Shared(program) => program.get_instructions(),
+        }
+    }
+}
+
 pub struct VirtualMachine {
-    instructions: Vec<Instruction>,
+    instructions: Code,
     program_counter: usize,
     pub(super) memory: Memory,
     pub(super) registers: Registers,
     stack: Vec<Immediate>,
     flags: Flags,
     cont: bool,
+    calling_convention: CallingConvention,
+    frames: Vec<CallFrame>,
+    loaded_functions: HashMap<FullIdentifier, usize>,
+    contexts: Vec<ExecutionContext>,
+    current_context: usize,
+    channels: Vec<Channel>,
+    schedule: Option<Schedule>,
+    shared_heap: SharedHeap,
+    interrupts: InterruptHandle,
+    interrupt_handlers: HashMap<u8, usize>,
+    interrupt_frames: Vec<InterruptFrame>,
+    timer: Option<Timer>,
+    symbols: SymbolTable,
 }
 
 pub static POINTER_SIZE: usize = std::mem::size_of::<usize>();
@@ -31,10 +83,44 @@ pub enum Fault {
     SegmentationFault,
     InvalidRegister,
     InvalidMemorySize,
-    InvalidAddressOfLocation(Literal),
+    InvalidAddressOfLocation(Box<Literal>),
     NotAVariable(String),
     TypeMismatch,
     InvalidField,
+    /// A callee register was changed by a call under the verifying convention
+    ClobberedRegister(u8),
+    /// A call returned with a value stack that was not as deep as when it was made
+    UnbalancedFrame,
+    /// A tail call was made from a procedure that had not entered exactly one local scope
+    UnbalancedScope,
+    /// A spilled argument was accessed outside of a managed call
+    NoCallFrame,
+    /// A special register that can not be written to was written to
+    ReadOnlyRegister(u8),
+    /// A computed jump or call targeted an address outside of the program
+    InvalidJumpTarget(usize),
+    /// A computed jump or call targeted a value that is not code
+    NotCallable,
+    /// Every execution context is waiting on another one
+    Deadlock,
+    /// An execution context was used that does not exist
+    InvalidContext(usize),
+    /// A channel was used that does not exist
+    InvalidChannel(usize),
+    /// A value that holds pointers was going to be shared between isolates
+    NotShareable,
+    /// An integer was divided by zero
+    DivideByZero,
+    /// A checked arithmetic operation had a result that does not fit in its type
+    ArithmeticOverflow,
+    /// A checked conversion lost information, or a value is not a valid char
+    InvalidConversion,
+    /// There is no interrupt with this number
+    InvalidInterrupt(u8),
+    /// An interrupt was delivered that has no handler
+    UnhandledInterrupt(u8),
+    /// No loaded module exports this function
+    UnresolvedSymbol(FullIdentifier),
 }
 
 impl Display for Fault {
@@ -48,16 +134,53 @@ impl Error for Fault {}
 impl VirtualMachine {
     pub fn new() -> Self {
         Self {
-            instructions: vec![],
+            instructions: Code::Owned(vec![]),
             program_counter: 0,
             memory: Memory::new(),
             registers: Registers::new(),
             stack: vec![],
             flags: Flags::new(),
             cont: true,
+            calling_convention: CallingConvention::Unchecked,
+            frames: vec![],
+            loaded_functions: HashMap::new(),
+            contexts: vec![],
+            current_context: MAIN_CONTEXT,
+            channels: vec![],
+            schedule: None,
+            shared_heap: SharedHeap::new(),
+            interrupts: InterruptHandle::new(),
+            interrupt_handlers: HashMap::new(),
+            interrupt_frames: vec![],
+            timer: None,
+            symbols: SymbolTable::default(),
         }
     }
 
+    /// Gets a handle that raises interrupts on this virtual machine
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupts.clone()
+    }
+
+    /// Sets the code address that handles an interrupt
+    pub fn set_interrupt_handler(&mut self, interrupt: u8, address: usize) {
+        self.interrupt_handlers.insert(interrupt, address);
+    }
+
+    /// Raises the timer interrupt every `interval` instructions, or stops the timer if `None`
+    pub fn set_timer(&mut self, interval: Option<usize>) {
+        self.timer = interval.filter(|interval| *interval > 0).map(Timer::new);
+    }
+
+    /// Uses a heap that is shared with other isolates, instead of a private one
+    pub fn set_shared_heap(&mut self, shared_heap: SharedHeap) {
+        self.shared_heap = shared_heap;
+    }
+
+    pub fn set_calling_convention(&mut self, calling_convention: CallingConvention) {
+        self.calling_convention = calling_convention;
+    }
+
     fn push(&mut self, val: Immediate) {
         self.stack.push(val);
     }
@@ -74,12 +197,292 @@ impl VirtualMachine {
         self.stack.last_mut().ok_or(Fault::SegmentationFault)
     }
 
+    fn argument_position(&self, num: u8) -> Result<usize, Fault> {
+        let frame = self
+            .frames
+            .last()
+            .filter(|_| self.calling_convention.is_managed())
+            .ok_or(Fault::NoCallFrame)?;
+        frame
+            .stack_base
+            .checked_sub(1 + num as usize)
+            .ok_or(Fault::SegmentationFault)
+    }
+
+    pub(crate) fn argument(&self, num: u8) -> Result<&Immediate, Fault> {
+        let position = self.argument_position(num)?;
+        Ok(&self.stack[position])
+    }
+
+    pub(crate) fn argument_mut(&mut self, num: u8) -> Result<&mut Immediate, Fault> {
+        let position = self.argument_position(num)?;
+        Ok(&mut self.stack[position])
+    }
+
+    fn enter_frame(&mut self) {
+        let saved_callee = if self.calling_convention.is_managed() {
+            Some(self.registers.callee.clone())
+        } else {
+            None
+        };
+        self.frames.push(CallFrame {
+            stack_base: self.stack.len(),
+            scope_depth: self.memory.scope_depth(),
+            saved_callee,
+        });
+    }
+
+    fn leave_frame(&mut self) -> Result<(), Fault> {
+        let frame = self.frames.pop();
+        if !self.calling_convention.is_managed() {
+            return Ok(());
+        }
+        let frame = frame.ok_or(Fault::InvalidReturn)?;
+        if self.stack.len() != frame.stack_base + 1 {
+            return Err(Fault::UnbalancedFrame);
+        }
+        let saved_callee = match frame.saved_callee {
+            Some(saved_callee) => saved_callee,
+            None => return Ok(()),
+        };
+        match self.calling_convention {
+            CallingConvention::Unchecked => {}
+            CallingConvention::Preserving => self.registers.callee = saved_callee,
+            CallingConvention::Verifying => {
+                let registers = self.registers.callee.iter().zip(saved_callee.iter());
+                for (num, (current, saved)) in registers.enumerate() {
+                    if !identical(current, saved) {
+                        return Err(Fault::ClobberedRegister(num as u8));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_register(&self, reg_type: RegisterType, reg: usize) -> Option<Immediate> {
         (match reg_type {
             RegisterType::Caller => self.registers.caller.get(reg),
             RegisterType::Callee => self.registers.callee.get(reg),
+            RegisterType::Special => return self.read_special(reg as u8).ok(),
         })
-        .map(|imm| imm.clone())
+        .cloned()
+    }
+
+    pub(crate) fn read_special(&self, num: u8) -> Result<Immediate, Fault> {
+        Ok(match SpecialRegister::try_from(num)? {
+            SpecialRegister::ProgramCounter => Immediate::USize(self.program_counter),
+            SpecialRegister::StackPointer => Immediate::USize(self.stack.len()),
+            SpecialRegister::FramePointer => {
+                Immediate::USize(self.frames.last().map_or(0, |frame| frame.stack_base))
+            }
+            SpecialRegister::Flags => Immediate::U16(self.flags.to_word()),
+        })
+    }
+
+    fn write_special(
+        &mut self,
+        num: u8,
+        imm: Immediate,
+        next_program_counter: &mut usize,
+    ) -> Result<(), Fault> {
+        let register = SpecialRegister::try_from(num)?;
+        if !register.is_writable() {
+            return Err(Fault::ReadOnlyRegister(num));
+        }
+        match (register, imm) {
+            (SpecialRegister::ProgramCounter, Immediate::USize(address)) => {
+                *next_program_counter = address;
+            }
+            (SpecialRegister::StackPointer, Immediate::USize(depth)) => {
+                if depth > self.stack.len() {
+                    return Err(Fault::SegmentationFault);
+                }
+                self.stack.truncate(depth);
+            }
+            (SpecialRegister::Flags, Immediate::U16(word)) => self.flags.set_word(word),
+            _ => return Err(PrimitiveTypeMismatch),
+        }
+        Ok(())
+    }
+
+    /// Appends the instructions of a function to the program the first time it is called, and
+    /// gets the address it starts at
+    fn load_function(&mut self, function: &Function) -> usize {
+        if let Some(address) = self.loaded_functions.get(function.get_identifier()) {
+            return *address;
+        }
+        let base = self.append(function.get_instructions());
+        self.loaded_functions
+            .insert(function.get_identifier().clone(), base);
+        base
+    }
+
+    /// Appends instructions to the program, relocating their targets, and gets the address they
+    /// start at
+    fn append(&mut self, instructions: &[Instruction]) -> usize {
+        let base = self.instructions.len();
+        for instruction in instructions {
+            let mut instruction = instruction.clone();
+            if let Some(target) = instruction.jump_target_mut() {
+                *target += base;
+            }
+            self.instructions.to_mut().push(instruction);
+        }
+        base
+    }
+
+    /// Appends a module to the program, and gets the address it starts at. Its functions can be
+    /// called by name once it is loaded, and its globals are declared in static memory.
+    pub fn load_module(&mut self, module: &Module) -> Result<usize, LinkError> {
+        module.validate()?;
+        let base = self.instructions.len();
+        self.symbols.add(module, base)?;
+        for (symbol, value) in module.globals() {
+            let name = symbol.to_string();
+            self.memory.declare_variable(&name, &Scope::Global);
+            if let Some(value) = value {
+                self.memory
+                    .set_variable(&name, value.clone())
+                    .expect("Global was just declared");
+            }
+        }
+        self.append(module.get_instructions());
+        Ok(base)
+    }
+
+    /// Resolves every `CallSymbol` in the program now, instead of the first time it is called
+    pub fn link(&mut self) -> Result<(), Vec<LinkError>> {
+        let mut errors = vec![];
+        for instruction in self.instructions.to_mut() {
+            if let Instruction::CallSymbol(symbol) = instruction {
+                match self.symbols.function(symbol) {
+                    Some(address) => *instruction = Instruction::Call(address),
+                    None => errors.push(LinkError::UnresolvedSymbol(symbol.clone())),
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The address of a function exported by a loaded module
+    pub fn address_of(&self, symbol: &FullIdentifier) -> Option<usize> {
+        self.symbols.function(symbol)
+    }
+
+    /// Resolves the target of an indirect jump or call to a code address
+    fn code_address(&mut self, target: Immediate) -> Result<usize, Fault> {
+        let address = match target {
+            Immediate::USize(address) => address,
+            Immediate::Function(function) => self.load_function(&function),
+            _ => return Err(Fault::NotCallable),
+        };
+        if address >= self.instructions.len() {
+            return Err(Fault::InvalidJumpTarget(address));
+        }
+        Ok(address)
+    }
+
+    fn call(&mut self, location: usize) -> usize {
+        self.enter_frame();
+        let program_counter = self.program_counter + 1;
+        let pc_imm = Immediate::USize(program_counter);
+        self.push(pc_imm);
+        location
+    }
+
+    /// Replaces the current procedure with the one at `location`, keeping its return address and
+    /// call frame. The current procedure must have entered its own local scope, and left only the
+    /// return address on the value stack.
+    fn tail_call(&mut self, location: usize) -> Result<usize, Fault> {
+        let frame = self.frames.last().ok_or(Fault::InvalidReturn)?;
+        if self.stack.len() != frame.stack_base + 1 {
+            return Err(Fault::UnbalancedFrame);
+        }
+        if !matches!(self.stack.last(), Some(Immediate::USize(_))) {
+            return Err(Fault::InvalidReturn);
+        }
+        if self.memory.scope_depth() != frame.scope_depth + 1 {
+            return Err(Fault::UnbalancedScope);
+        }
+        self.memory.exit_local_scope();
+        Ok(location)
+    }
+
+    /// Swaps the running context with the saved context `id`
+    fn swap_context(&mut self, id: usize) {
+        let context = &mut self.contexts[id];
+        std::mem::swap(&mut self.program_counter, &mut context.program_counter);
+        std::mem::swap(&mut self.stack, &mut context.stack);
+        std::mem::swap(&mut self.registers, &mut context.registers);
+        std::mem::swap(&mut self.flags, &mut context.flags);
+        std::mem::swap(&mut self.frames, &mut context.frames);
+        std::mem::swap(&mut self.memory, &mut context.memory);
+        std::mem::swap(&mut self.interrupt_frames, &mut context.interrupt_frames);
+    }
+
+    /// Saves the current state and jumps to the handler of an interrupt, with interrupts and
+    /// tracing disabled
+    fn enter_interrupt(&mut self, interrupt: u8) -> Result<(), Fault> {
+        let handler = *self
+            .interrupt_handlers
+            .get(&interrupt)
+            .ok_or(Fault::UnhandledInterrupt(interrupt))?;
+        self.interrupt_frames.push(InterruptFrame {
+            program_counter: self.program_counter,
+            flags: self.flags.to_word(),
+            stack_depth: self.stack.len(),
+        });
+        self.flags.interrupt_enable = false;
+        self.flags.trap = false;
+        self.program_counter = handler;
+        Ok(())
+    }
+
+    /// Counts an instruction towards the timer, and traps if the instruction ran while the trap
+    /// flag was set. Traps can not be disabled.
+    fn tick(&mut self, traced: bool) -> Result<(), Fault> {
+        if let Some(timer) = &mut self.timer {
+            if timer.tick() {
+                self.interrupts.raise(TIMER_INTERRUPT)?;
+            }
+        }
+        if traced && self.cont {
+            self.enter_interrupt(TRAP_INTERRUPT)?;
+        }
+        Ok(())
+    }
+
+    /// Switches to the next ready context after the current one, in round robin order
+    fn switch_context(&mut self) {
+        let count = self.contexts.len();
+        let next = (1..=count)
+            .map(|offset| (self.current_context + offset) % count)
+            .find(|id| matches!(self.contexts[*id].state, ContextState::Ready))
+            .unwrap_or(self.current_context);
+        if next != self.current_context {
+            self.swap_context(self.current_context);
+            self.swap_context(next);
+            self.current_context = next;
+        }
+    }
+
+    fn ready_contexts(&self) -> usize {
+        self.contexts
+            .iter()
+            .filter(|context| matches!(context.state, ContextState::Ready))
+            .count()
+    }
+
+    fn channel(&mut self, id: &Immediate) -> Result<&mut Channel, Fault> {
+        match id {
+            Immediate::USize(id) => self.channels.get_mut(*id).ok_or(Fault::InvalidChannel(*id)),
+            _ => Err(PrimitiveTypeMismatch),
+        }
     }
 
     fn run_instruction(&mut self, instruction: &Instruction) -> Result<(), Fault> {
@@ -91,19 +494,34 @@ impl VirtualMachine {
             }
             Instruction::PopTo(dest) => {
                 let imm = self.pop()?;
-                let mut dest = dest.clone();
-                let dest_imm = dest.get_immediate_mut(self)?;
-                *dest_imm = imm;
+                if let Literal::Register(RegisterType::Special, num) = dest {
+                    self.write_special(*num, imm, &mut next_program_counter)?;
+                } else {
+                    let mut dest = dest.clone();
+                    let dest_imm = dest.get_immediate_mut(self)?;
+                    *dest_imm = imm;
+                }
             }
             Instruction::Ret(option) => {
+                let managed = self.calling_convention.is_managed();
+                // A managed return value is read in the callee's frame, before its callee bank is
+                // restored
+                let returned = match option {
+                    Some(src) if managed => Some(src.get_immediate(self)?),
+                    _ => None,
+                };
+                self.leave_frame()?;
                 let ret_location: Immediate = self.pop()?;
                 if let Immediate::USize(ret_pos_ptr) = ret_location {
                     next_program_counter = ret_pos_ptr;
                 } else {
                     return Err(Fault::InvalidReturn);
                 }
-                if let Some(imm) = option {
-                    self.push(imm.get_immediate(self)?);
+                if let Some(imm) = returned {
+                    self.registers.caller[RETURN_REGISTER as usize] = imm;
+                } else if let (Some(src), false) = (option, managed) {
+                    let imm = src.get_immediate(self)?;
+                    self.push(imm);
                 }
             }
             Instruction::Jump(counter) => {
@@ -116,11 +534,24 @@ impl VirtualMachine {
                     comparison.perform_op(&mut self.flags, val1, val2)?;
                 self.push(returned_value);
             }
-            Instruction::PerformOperation(operation) => {
+            Instruction::PerformOperation(operation, mode) => {
                 let val1: Immediate = self.pop()?;
                 let val2: Immediate = self.pop()?;
                 let returned_value: Immediate =
-                    operation.perform_op(&mut self.flags, val1, val2)?;
+                    operation.perform_op(*mode, &mut self.flags, val1, val2)?;
+                self.push(returned_value);
+            }
+            Instruction::PerformUnaryOperation(operation) => {
+                let val: Immediate = self.pop()?;
+                let returned_value: Immediate = operation.perform_op(&mut self.flags, val)?;
+                self.push(returned_value);
+            }
+            Instruction::FusedMultiplyAdd => {
+                let val1: Immediate = self.pop()?;
+                let val2: Immediate = self.pop()?;
+                let val3: Immediate = self.pop()?;
+                let returned_value: Immediate =
+                    fused_multiply_add(&mut self.flags, val1, val2, val3)?;
                 self.push(returned_value);
             }
             Instruction::AddressOf(location) => match location {
@@ -130,7 +561,7 @@ impl VirtualMachine {
                     self.push(pointer);
                 }
                 _ => {
-                    return Err(Fault::InvalidAddressOfLocation(location.clone()));
+                    return Err(Fault::InvalidAddressOfLocation(Box::new(location.clone())));
                 }
             },
             Instruction::Dereference => {
@@ -145,34 +576,14 @@ impl VirtualMachine {
                 self.push(unsafe { (*immediate).clone() })
             }
             Instruction::Call(location) => {
-                let program_counter = self.program_counter + 1;
-                let pc_imm = Immediate::USize(program_counter);
-                self.push(pc_imm);
-                next_program_counter = *location;
+                next_program_counter = self.call(*location);
             }
             Instruction::Throw(imm) => {
                 unimplemented!("Throwing has not been implemented yet");
             }
             Instruction::Catch => {}
             Instruction::ConditionalJump(jump_type, location) => {
-                let _ = self.pop()?;
-                let cond = match jump_type {
-                    JumpType::Zero | JumpType::Equal => self.flags.zero,
-                    JumpType::NotZero | JumpType::NotEqual => !self.flags.zero,
-                    JumpType::Greater => self.flags.zero == self.flags.sign && !self.flags.zero,
-                    JumpType::GreaterEqual => self.flags.zero == self.flags.sign || self.flags.zero,
-                    JumpType::Above => !self.flags.carry && !self.flags.zero,
-                    JumpType::AboveEqual => !self.flags.carry || self.flags.zero,
-                    JumpType::Lesser => self.flags.sign != self.flags.zero,
-                    JumpType::LessEqual => self.flags.sign != self.flags.zero || self.flags.zero,
-                    JumpType::Below => self.flags.carry,
-                    JumpType::BelowEqual => self.flags.carry || self.flags.zero,
-                    JumpType::Overflow => self.flags.overflow,
-                    JumpType::NotOverflow => !self.flags.overflow,
-                    JumpType::Signed => self.flags.sign,
-                    JumpType::NotSigned => !self.flags.sign,
-                };
-                if cond {
+                if jump_type.is_taken(&self.flags) {
                     next_program_counter = *location;
                 }
             }
@@ -184,8 +595,12 @@ impl VirtualMachine {
             Instruction::Halt => self.cont = false,
             Instruction::Move { dest, src } => {
                 let immediate = src.get_immediate(self)?;
-                let imm: &mut Immediate = dest.get_immediate_mut_from_immutable(self)?;
-                *imm = immediate;
+                if let Literal::Register(RegisterType::Special, num) = dest {
+                    self.write_special(*num, immediate, &mut next_program_counter)?;
+                } else {
+                    let imm: &mut Immediate = dest.get_immediate_mut_from_immutable(self)?;
+                    *imm = immediate;
+                }
             }
             Instruction::DeclareVar(name, scope) => {
                 self.memory.declare_variable(name, scope);
@@ -200,14 +615,6 @@ impl VirtualMachine {
             Instruction::Coerce { dest_type } => {
                 let src: Immediate = self.pop()?;
                 let imm = match dest_type {
-                    U8(_) => src.into_u8(),
-                    U16(_) => src.into_u16(),
-                    U32(_) => src.into_u32(),
-                    U64(_) => src.into_u64(),
-                    Immediate::USize(_) => src.into_usize(),
-                    Float(_) => src.into_float(),
-                    Double(_) => src.into_double(),
-                    Immediate::Char(_) => src.into_char(),
                     Immediate::Pointer(_) => {
                         if let Immediate::Pointer(_) = &src {
                             src
@@ -224,10 +631,14 @@ impl VirtualMachine {
                             return Err(PrimitiveTypeMismatch);
                         }
                     }
-                    _ => return Err(PrimitiveTypeMismatch),
+                    dest_type => src.convert(dest_type, ConversionKind::Truncating)?,
                 };
                 self.push(imm);
             }
+            Instruction::Convert { dest_type, kind } => {
+                let src: Immediate = self.pop()?;
+                self.push(src.convert(dest_type, *kind)?);
+            }
             Instruction::Enter => {
                 self.memory.new_local_scope();
             }
@@ -237,7 +648,10 @@ impl VirtualMachine {
             Instruction::Exit => {
                 self.memory.exit_local_scope();
             }
-            Instruction::CallFunction(_) => unimplemented!(),
+            Instruction::CallFunction(function) => {
+                let location = self.load_function(function);
+                next_program_counter = self.call(location);
+            }
             Instruction::GetField(location, field_name) => {
                 let mut location = location.clone();
                 let imm: &mut Immediate = location.get_immediate_mut(self)?;
@@ -292,6 +706,126 @@ impl VirtualMachine {
                 let pointer = self.memory.heapify(imm);
                 self.push(Immediate::Pointer(pointer));
             }
+            Instruction::CallIndirect => {
+                let target = self.pop()?;
+                let location = if let Immediate::Closure(closure) = target {
+                    let location = self.load_function(closure.get_function());
+                    self.memory.new_closure_scope(closure.get_environment());
+                    location
+                } else {
+                    self.code_address(target)?
+                };
+                next_program_counter = self.call(location);
+            }
+            Instruction::JumpIndirect => {
+                let target = self.pop()?;
+                next_program_counter = self.code_address(target)?;
+            }
+            Instruction::TailCall(location) => {
+                next_program_counter = self.tail_call(*location)?;
+            }
+            Instruction::TailCallFunction(function) => {
+                let location = self.load_function(function);
+                next_program_counter = self.tail_call(location)?;
+            }
+            Instruction::Spawn(location) => {
+                let registers = Registers {
+                    caller: self.registers.caller.clone(),
+                    callee: Registers::new().callee,
+                };
+                let memory = self.memory.new_sharing_statics();
+                self.contexts
+                    .push(ExecutionContext::new(*location, registers, memory));
+                self.push(Immediate::USize(self.contexts.len() - 1));
+            }
+            Instruction::Yield => self.schedule = Some(Schedule::Yield),
+            Instruction::Join => {
+                let id = match self.peak()? {
+                    Immediate::USize(id) => *id,
+                    _ => return Err(PrimitiveTypeMismatch),
+                };
+                let context = self.contexts.get(id).ok_or(Fault::InvalidContext(id))?;
+                match &context.state {
+                    ContextState::Finished(result) => {
+                        let result = result.clone();
+                        self.pop()?;
+                        self.push(result);
+                    }
+                    ContextState::Ready => {
+                        next_program_counter = self.program_counter;
+                        self.schedule = Some(Schedule::Block);
+                    }
+                }
+            }
+            Instruction::MakeChannel => {
+                self.channels.push(Channel::new());
+                self.push(Immediate::USize(self.channels.len() - 1));
+            }
+            Instruction::Send => {
+                let value = self.pop()?;
+                let id = self.pop()?;
+                self.channel(&id)?.push_back(value);
+            }
+            Instruction::Receive => {
+                let id = self.peak()?.clone();
+                match self.channel(&id)?.pop_front() {
+                    Some(value) => {
+                        self.pop()?;
+                        self.push(value);
+                    }
+                    None => {
+                        next_program_counter = self.program_counter;
+                        self.schedule = Some(Schedule::Block);
+                    }
+                }
+            }
+            Instruction::SharedStore(name) => {
+                let value = SharedValue::try_from(self.pop()?)?;
+                self.shared_heap.store(name, value);
+            }
+            Instruction::SharedLoad(name) => {
+                let value = self
+                    .shared_heap
+                    .load(name)
+                    .ok_or_else(|| Fault::NotAVariable(name.clone()))?;
+                self.push(value.into());
+            }
+            Instruction::CallSymbol(symbol) => {
+                let address = self
+                    .address_of(symbol)
+                    .ok_or_else(|| Fault::UnresolvedSymbol(symbol.clone()))?;
+                // Later calls from here skip the lookup
+                self.instructions.to_mut()[self.program_counter] =
+                    Instruction::Call(address);
+                next_program_counter = self.call(address);
+            }
+            Instruction::EnableInterrupts => self.flags.interrupt_enable = true,
+            Instruction::DisableInterrupts => self.flags.interrupt_enable = false,
+            Instruction::ReturnFromInterrupt => {
+                let frame = self.interrupt_frames.pop().ok_or(Fault::InvalidReturn)?;
+                if self.stack.len() != frame.stack_depth {
+                    return Err(Fault::UnbalancedFrame);
+                }
+                self.flags.set_word(frame.flags);
+                next_program_counter = frame.program_counter;
+            }
+            Instruction::SharedCompareSwap(name) => {
+                let new = SharedValue::try_from(self.pop()?)?;
+                let expected = SharedValue::try_from(self.pop()?)?;
+                let (current, swapped) = self.shared_heap.compare_and_swap(name, &expected, new);
+                let current = current.ok_or_else(|| Fault::NotAVariable(name.clone()))?;
+                self.flags.zero = swapped;
+                self.push(current.into());
+            }
+            Instruction::MakeClosure { function, captures } => {
+                let mut environment = Vec::with_capacity(captures.len());
+                for name in captures {
+                    let ptr = self.memory.capture_variable(name)?;
+                    environment.push((name.clone(), ptr));
+                }
+                let closure = Closure::new(function.clone(), environment);
+                self.push(Immediate::Closure(closure));
+            }
         }
         self.program_counter = next_program_counter;
         Ok(())
@@ -300,34 +834,91 @@ impl VirtualMachine {
     pub fn execute(&mut self, instructions: Vec<Instruction>, start: usize) -> Result<u32, Fault> {
         self.flags.reset();
         self.program_counter = start;
-        self.instructions = instructions;
-        while self.cont {
-            let instruction = self
-                .instructions
-                .get(self.program_counter)
-                .ok_or_else(|| SegmentationFault)?
-                .clone();
-            self.run_instruction(&instruction)?;
-        }
-        match self.pop()? {
-            U32(exit) => Ok(exit),
-            _ => Err(Fault::PrimitiveTypeMismatch),
-        }
+        self.instructions = Code::Owned(instructions);
+        self.loaded_functions.clear();
+        self.symbols.clear();
+        self.run()
+    }
+
+    /// Runs a program without copying its instructions, until a function is loaded
+    pub fn execute_program(&mut self, program: &Program, start: usize) -> Result<u32, Fault> {
+        self.flags.reset();
+        self.program_counter = start;
+        self.instructions = Code::Shared(program.clone());
+        self.loaded_functions.clear();
+        self.symbols.clear();
+        self.run()
+    }
+
+    /// Runs the loaded modules from an exported function, which halts when it is done
+    pub fn execute_symbol(&mut self, entry: &FullIdentifier) -> Result<u32, Fault> {
+        let address = self
+            .address_of(entry)
+            .ok_or_else(|| Fault::UnresolvedSymbol(entry.clone()))?;
+        self.flags.reset();
+        self.program_counter = address;
+        self.run()
     }
 
     pub fn headless_execute(instructions: Vec<Instruction>, start: usize) -> Result<u32, Fault> {
         let mut vm = VirtualMachine::new();
         vm.program_counter = start;
-        vm.instructions = instructions;
-        while vm.cont {
-            let instruction = vm
+        vm.instructions = Code::Owned(instructions);
+        vm.run()
+    }
+
+    /// Runs until the main context halts, switching between contexts whenever the running one
+    /// yields, blocks or halts
+    fn run(&mut self) -> Result<u32, Fault> {
+        let placeholder = ExecutionContext::new(0, Registers::new(), Memory::new());
+        self.contexts = vec![placeholder];
+        self.current_context = MAIN_CONTEXT;
+        self.channels.clear();
+        self.interrupt_frames.clear();
+        self.cont = true;
+        let mut blocked = 0;
+        loop {
+            if !self.cont {
+                if self.current_context == MAIN_CONTEXT {
+                    break;
+                }
+                let result = self.pop()?;
+                self.contexts[self.current_context].state = ContextState::Finished(result);
+                self.cont = true;
+                blocked = 0;
+                self.switch_context();
+                continue;
+            }
+            if self.flags.interrupt_enable {
+                if let Some(interrupt) = self.interrupts.take() {
+                    self.enter_interrupt(interrupt)?;
+                }
+            }
+
+            let instruction = self
                 .instructions
-                .get(vm.program_counter)
+                .get(self.program_counter)
                 .ok_or_else(|| SegmentationFault)?
                 .clone();
-            vm.run_instruction(&instruction)?;
+            let traced = self.flags.trap;
+            self.run_instruction(&instruction)?;
+            self.tick(traced)?;
+            match self.schedule.take() {
+                None => blocked = 0,
+                Some(Schedule::Yield) => {
+                    blocked = 0;
+                    self.switch_context();
+                }
+                Some(Schedule::Block) => {
+                    blocked += 1;
+                    if blocked >= self.ready_contexts() {
+                        return Err(Fault::Deadlock);
+                    }
+                    self.switch_context();
+                }
+            }
         }
-        match vm.pop()? {
+        match self.pop()? {
             U32(exit) => Ok(exit),
             _ => Err(Fault::PrimitiveTypeMismatch),
         }
@@ -0,0 +1,147 @@
+use virtual_machine::calling_convention::CallingConvention;
+use virtual_machine::instruction_set::Immediate::{USize, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::{Callee, Caller};
+use virtual_machine::instruction_set::{ArithmeticMode, Instruction, JumpType, Literal, Operation};
+use virtual_machine::memory::Scope::Local;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+fn fib(n: usize) -> u32 {
+    match n {
+        0 | 1 => n as u32,
+        _ => fib(n - 1) + fib(n - 2),
+    }
+}
+
+const START: usize = 28;
+
+/// Fibonacci following the calling convention: the argument and result are passed in `Caller 0`,
+/// and `Callee 0` is relied on to survive the second recursive call
+fn fib_program(n: usize) -> Vec<Instruction> {
+    vec![
+        Enter,
+        DeclareVar("n".to_string(), Local),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        SaveVar("n".to_string()),
+        PushVal(USize(2)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        Pop,
+        ConditionalJump(JumpType::Below, 26),
+        PushVal(USize(1)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        Call(0),
+        Move {
+            dest: Literal::Register(Callee, 0),
+            src: Literal::Register(Caller, 0),
+        },
+        PushVal(USize(2)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        Call(0),
+        Push {
+            src: Literal::Register(Callee, 0),
+        },
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        Exit,
+        Ret(None),
+        Exit,
+        Ret(None),
+        Move {
+            dest: Literal::Register(Caller, 0),
+            src: Literal::Immediate(USize(n)),
+        },
+        Call(0),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ]
+}
+
+#[test]
+fn preserving_restores_callee_registers() {
+    for n in 0..12 {
+        let mut vm = VirtualMachine::new();
+        vm.set_calling_convention(CallingConvention::Preserving);
+        let result = vm.execute(fib_program(n), START);
+        assert_eq!(result.unwrap(), fib(n));
+    }
+}
+
+#[test]
+fn preserving_returns_callee_registers() {
+    let instructions = vec![
+        Move {
+            dest: Literal::Register(Callee, 0),
+            src: Literal::Immediate(U32(7)),
+        },
+        Ret(Some(Literal::Register(Callee, 0))),
+        Move {
+            dest: Literal::Register(Callee, 0),
+            src: Literal::Immediate(U32(1)),
+        },
+        Call(0),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Halt,
+    ];
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Preserving);
+    assert_eq!(vm.execute(instructions, 2).unwrap(), 7);
+}
+
+#[test]
+fn verifying_flags_clobbered_registers() {
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Verifying);
+    let result = vm.execute(fib_program(5), START);
+    assert!(matches!(result, Err(Fault::ClobberedRegister(0))));
+}
+
+#[test]
+fn verifying_flags_unbalanced_stack() {
+    let instructions = vec![PushVal(U32(1)), Ret(None), Call(0), PushVal(U32(0)), Halt];
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Verifying);
+    let result = vm.execute(instructions, 2);
+    assert!(matches!(result, Err(Fault::UnbalancedFrame)));
+}
+
+#[test]
+fn spilled_arguments() {
+    let instructions = vec![
+        Push {
+            src: Literal::Argument(1),
+        },
+        Push {
+            src: Literal::Argument(0),
+        },
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        Ret(None),
+        PushVal(U32(3)),
+        PushVal(U32(10)),
+        Call(0),
+        Pop,
+        Pop,
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Halt,
+    ];
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Verifying);
+    assert_eq!(vm.execute(instructions, 5).unwrap(), 7);
+}
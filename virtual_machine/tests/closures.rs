@@ -0,0 +1,94 @@
+use virtual_machine::instruction_set::Immediate::U32;
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Literal, Operation};
+use virtual_machine::memory::Scope::Local;
+use virtual_machine::resolution::functions::{Function, FunctionBuilder};
+use virtual_machine::resolution::FullIdentifier;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+/// Returns the value of the captured variable `name` in `Caller 0`
+fn getter(name: &str) -> Function {
+    FunctionBuilder::with_name(FullIdentifier::from("get"))
+        .no_parameters()
+        .with_instructions(vec![
+            GetVar(name.to_string()),
+            PopTo(Literal::Register(Caller, 0)),
+            Exit,
+            Ret(None),
+        ])
+        .build()
+}
+
+#[test]
+fn captured_variable_escapes_scope() {
+    let instructions = vec![
+        Enter,
+        DeclareVar("x".to_string(), Local),
+        PushVal(U32(5)),
+        SaveVar("x".to_string()),
+        MakeClosure {
+            function: getter("x"),
+            captures: vec!["x".to_string()],
+        },
+        Exit,
+        CallIndirect,
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        5
+    );
+}
+
+#[test]
+fn captured_variable_is_shared() {
+    let increment = FunctionBuilder::with_name(FullIdentifier::from("increment"))
+        .no_parameters()
+        .with_instructions(vec![
+            PushVal(U32(1)),
+            GetVar("count".to_string()),
+            PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+            SaveVar("count".to_string()),
+            Exit,
+            Ret(None),
+        ])
+        .build();
+    let instructions = vec![
+        DeclareVar("count".to_string(), Local),
+        PushVal(U32(10)),
+        SaveVar("count".to_string()),
+        MakeClosure {
+            function: increment,
+            captures: vec!["count".to_string()],
+        },
+        DeclareVar("counter".to_string(), Local),
+        SaveVar("counter".to_string()),
+        GetVar("counter".to_string()),
+        CallIndirect,
+        GetVar("counter".to_string()),
+        CallIndirect,
+        GetVar("count".to_string()),
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        12
+    );
+}
+
+#[test]
+fn capturing_missing_variable() {
+    let instructions = vec![
+        MakeClosure {
+            function: getter("y"),
+            captures: vec!["y".to_string()],
+        },
+        Halt,
+    ];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::NotAVariable(_))));
+}
@@ -2,8 +2,11 @@ use virtual_machine::instruction_set::Immediate::U32;
 use virtual_machine::instruction_set::Instruction::*;
 use virtual_machine::instruction_set::Literal;
 use virtual_machine::instruction_set::RegisterType::{Callee, Caller};
-use virtual_machine::instruction_set::{Immediate, JumpType, Operation};
+use virtual_machine::instruction_set::{
+    ArithmeticMode, Immediate, Instruction, JumpType, Operation,
+};
 use virtual_machine::memory::Scope::Local;
+use virtual_machine::optimization::peephole::PeepholeOptimizer;
 use virtual_machine::vm::VirtualMachine;
 
 fn fib(n: usize) -> u32 {
@@ -13,67 +16,90 @@ fn fib(n: usize) -> u32 {
     }
 }
 
+const START: usize = 47 - 13;
+
+fn fib_program(n: usize) -> Vec<Instruction> {
+    vec![
+        Enter,
+        DeclareVar("n".to_string(), Local),
+        Push {
+            src: Literal::Register(Callee, 0),
+        },
+        SaveVar("n".to_string()),
+        PushVal(Immediate::USize(2)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        Pop,
+        ConditionalJump(JumpType::Below, 42 - 13),
+        Push {
+            src: Literal::Register(Callee, 0),
+        },
+        PushVal(Immediate::USize(2)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Callee, 0)),
+        Push {
+            src: Literal::Register(Callee, 1),
+        },
+        Call(0),
+        PopTo(Literal::Register(Callee, 1)),
+        PushVal(Immediate::USize(1)),
+        GetVar("n".to_string()),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Callee, 0)),
+        Call(0),
+        Push {
+            src: Literal::Register(Callee, 1),
+        },
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        PopTo(Literal::Register(Callee, 1)),
+        PopTo(Literal::Register(Callee, 0)),
+        Exit,
+        Ret(Some(Literal::Register(Caller, 0))),
+        Exit,
+        Move {
+            dest: Literal::Register(Caller, 0),
+            src: Literal::Register(Callee, 0),
+        },
+        Ret(Some(Literal::Register(Caller, 0))),
+        Nop,
+        Nop,
+        Nop,
+        Nop,
+        Move {
+            dest: Literal::Register(Callee, 0),
+            src: Literal::Immediate(Immediate::USize(n)),
+        },
+        Call(0),
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ]
+}
+
 #[test]
 fn fib_test() {
     for n in 0..16 {
-        let instructions = vec![
-            Enter,
-            DeclareVar("n".to_string(), Local),
-            Push {
-                src: Literal::Register(Callee, 0),
-            },
-            SaveVar("n".to_string()),
-            PushVal(Immediate::USize(2)),
-            GetVar("n".to_string()),
-            PerformOperation(Operation::Subtract),
-            ConditionalJump(JumpType::Below, 41 - 13),
-            Push {
-                src: Literal::Register(Callee, 0),
-            },
-            PushVal(Immediate::USize(2)),
-            GetVar("n".to_string()),
-            PerformOperation(Operation::Subtract),
-            PopTo(Literal::Register(Callee, 0)),
-            Push {
-                src: Literal::Register(Callee, 1),
-            },
-            Call(0),
-            PopTo(Literal::Register(Callee, 1)),
-            PushVal(Immediate::USize(1)),
-            GetVar("n".to_string()),
-            PerformOperation(Operation::Subtract),
-            PopTo(Literal::Register(Callee, 0)),
-            Call(0),
-            Push {
-                src: Literal::Register(Callee, 1),
-            },
-            PerformOperation(Operation::Add),
-            PopTo(Literal::Register(Caller, 0)),
-            PopTo(Literal::Register(Callee, 1)),
-            PopTo(Literal::Register(Callee, 0)),
-            Exit,
-            Ret(Some(Literal::Register(Caller, 0))),
-            Exit,
-            Move {
-                dest: Literal::Register(Caller, 0),
-                src: Literal::Register(Callee, 0),
-            },
-            Ret(Some(Literal::Register(Caller, 0))),
-            Nop,
-            Nop,
-            Nop,
-            Nop,
-            Move {
-                dest: Literal::Register(Callee, 0),
-                src: Literal::Immediate(Immediate::USize(n)),
-            },
-            Call(0),
-            Coerce { dest_type: U32(0) },
-            Halt,
-        ];
+        let instructions = fib_program(n);
 
-        let result = VirtualMachine::headless_execute(instructions, 46 - 13);
+        let result = VirtualMachine::headless_execute(instructions, START);
         println!("Result = {:?}", result);
         assert_eq!(result.unwrap(), fib(n));
     }
 }
+
+#[test]
+fn fib_optimized_test() {
+    for n in 0..16 {
+        let instructions = fib_program(n);
+        let original_length = instructions.len();
+
+        let optimized = PeepholeOptimizer::new(START).optimize(instructions);
+        let start = optimized.relocate(START);
+        let instructions = optimized.into_instructions();
+        assert!(instructions.len() < original_length);
+
+        let result = VirtualMachine::headless_execute(instructions, start);
+        assert_eq!(result.unwrap(), fib(n));
+    }
+}
@@ -0,0 +1,429 @@
+use virtual_machine::flags::Flags;
+use virtual_machine::instruction_set::Immediate::{U16, U32, U64, U8};
+use virtual_machine::instruction_set::{
+    ArithmeticMode, ComparisonOperation, Immediate, JumpType, Operation,
+};
+use virtual_machine::vm::Fault;
+
+const OPERATIONS: [Operation; 16] = [
+    Operation::Add,
+    Operation::Subtract,
+    Operation::Multiply,
+    Operation::Divide,
+    Operation::Remainder,
+    Operation::And,
+    Operation::Or,
+    Operation::Xor,
+    Operation::ShiftLeft,
+    Operation::ShiftRight,
+    Operation::ArithmeticShiftRight,
+    Operation::RotateLeft,
+    Operation::RotateRight,
+    Operation::Minimum,
+    Operation::Maximum,
+    Operation::Power,
+];
+
+const COMPARISONS: [ComparisonOperation; 11] = [
+    ComparisonOperation::And,
+    ComparisonOperation::Or,
+    ComparisonOperation::LessThan,
+    ComparisonOperation::GreaterThan,
+    ComparisonOperation::LessThanEqual,
+    ComparisonOperation::GreaterThanEqual,
+    ComparisonOperation::Above,
+    ComparisonOperation::AboveEqual,
+    ComparisonOperation::Below,
+    ComparisonOperation::BelowEqual,
+    ComparisonOperation::Compare,
+];
+
+const JUMPS: [JumpType; 18] = [
+    JumpType::Zero,
+    JumpType::NotZero,
+    JumpType::Equal,
+    JumpType::NotEqual,
+    JumpType::Greater,
+    JumpType::GreaterEqual,
+    JumpType::Above,
+    JumpType::AboveEqual,
+    JumpType::Lesser,
+    JumpType::LessEqual,
+    JumpType::Below,
+    JumpType::BelowEqual,
+    JumpType::Overflow,
+    JumpType::NotOverflow,
+    JumpType::Signed,
+    JumpType::NotSigned,
+    JumpType::Parity,
+    JumpType::NotParity,
+];
+
+/// The result of an operation in native Rust, with the carry and overflow flags it should set
+struct Expected {
+    value: u64,
+    carry: bool,
+    overflow: bool,
+}
+
+impl Expected {
+    fn new(value: u64, carry: bool, overflow: bool) -> Self {
+        Expected {
+            value,
+            carry,
+            overflow,
+        }
+    }
+
+    fn plain(value: u64) -> Self {
+        Expected::new(value, false, false)
+    }
+}
+
+/// Generates the native reference model of the operations for an unsigned type and its signed
+/// counterpart. `None` means the operation should fault.
+macro_rules! reference {
+    ($name:ident, $unsigned:ty, $signed:ty, $variant:ident) => {
+        mod $name {
+            use super::*;
+
+            const BITS: u32 = <$unsigned>::BITS;
+
+            pub fn immediate(value: u64) -> Immediate {
+                $variant(value as $unsigned)
+            }
+
+            /// Shift amounts are clamped so that amounts that do not fit in a `u32` behave the
+            /// same as amounts beyond the width
+            fn amount(b: $unsigned) -> u32 {
+                if b as u64 > 1024 {
+                    1024
+                } else {
+                    b as u32
+                }
+            }
+
+            pub fn operation(operation: Operation, a: u64, b: u64) -> Option<Expected> {
+                let (a, b) = (a as $unsigned, b as $unsigned);
+                let wide = a as u128;
+                Some(match operation {
+                    Operation::Add => {
+                        let (value, carry) = a.overflowing_add(b);
+                        let overflow = (a as $signed).overflowing_add(b as $signed).1;
+                        Expected::new(value as u64, carry, overflow)
+                    }
+                    Operation::Subtract => {
+                        let (value, carry) = a.overflowing_sub(b);
+                        let overflow = (a as $signed).overflowing_sub(b as $signed).1;
+                        Expected::new(value as u64, carry, overflow)
+                    }
+                    Operation::Multiply => {
+                        let (value, carry) = a.overflowing_mul(b);
+                        Expected::new(value as u64, carry, carry)
+                    }
+                    Operation::Divide => Expected::plain(a.checked_div(b)? as u64),
+                    Operation::Remainder => Expected::plain(a.checked_rem(b)? as u64),
+                    Operation::And => Expected::plain((a & b) as u64),
+                    Operation::Or => Expected::plain((a | b) as u64),
+                    Operation::Xor => Expected::plain((a ^ b) as u64),
+                    Operation::ShiftLeft => {
+                        let amount = amount(b);
+                        let value = a.checked_shl(amount).unwrap_or(0);
+                        let shifted = wide.checked_shl(amount).unwrap_or(0);
+                        let carry = amount > 0 && (shifted >> BITS) & 1 == 1;
+                        Expected::new(value as u64, carry, false)
+                    }
+                    Operation::ShiftRight => {
+                        let amount = amount(b);
+                        let value = a.checked_shr(amount).unwrap_or(0);
+                        let carry =
+                            amount > 0 && wide.checked_shr(amount - 1).unwrap_or(0) & 1 == 1;
+                        Expected::new(value as u64, carry, false)
+                    }
+                    Operation::ArithmeticShiftRight => {
+                        let amount = amount(b);
+                        let value = (a as $signed) >> amount.min(BITS - 1);
+                        let extended = a as $signed as i128;
+                        let carry = amount > 0 && (extended >> (amount - 1).min(127)) & 1 == 1;
+                        Expected::new(value as $unsigned as u64, carry, false)
+                    }
+                    Operation::RotateLeft => {
+                        let value = a.rotate_left((b % BITS as $unsigned) as u32);
+                        Expected::new(value as u64, value & 1 == 1, false)
+                    }
+                    Operation::RotateRight => {
+                        let value = a.rotate_right((b % BITS as $unsigned) as u32);
+                        Expected::new(value as u64, value >> (BITS - 1) == 1, false)
+                    }
+                    Operation::Minimum => Expected::plain(a.min(b) as u64),
+                    Operation::Maximum => Expected::plain(a.max(b) as u64),
+                    Operation::Power => {
+                        // a^b is a^(2^32 * high) * a^low, for exponents that do not fit a u32
+                        let (high, low) = ((b as u64 >> 32) as u32, b as u32);
+                        let value = a
+                            .wrapping_pow(1 << 16)
+                            .wrapping_pow(1 << 16)
+                            .wrapping_pow(high)
+                            .wrapping_mul(a.wrapping_pow(low));
+                        let carry = if high > 0 {
+                            a > 1
+                        } else {
+                            a.overflowing_pow(low).1
+                        };
+                        Expected::new(value as u64, carry, carry)
+                    }
+                })
+            }
+
+            pub fn comparison(comparison: ComparisonOperation, a: u64, b: u64) -> Expected {
+                let (a, b) = (a as $unsigned, b as $unsigned);
+                let (sa, sb) = (a as $signed, b as $signed);
+                let truth = match comparison {
+                    ComparisonOperation::And => a != 0 && b != 0,
+                    ComparisonOperation::Or => a != 0 || b != 0,
+                    ComparisonOperation::LessThan => sa < sb,
+                    ComparisonOperation::GreaterThan => sa > sb,
+                    ComparisonOperation::LessThanEqual => sa <= sb,
+                    ComparisonOperation::GreaterThanEqual => sa >= sb,
+                    ComparisonOperation::Above => a > b,
+                    ComparisonOperation::AboveEqual => a >= b,
+                    ComparisonOperation::Below => a < b,
+                    ComparisonOperation::BelowEqual => a <= b,
+                    ComparisonOperation::Compare => {
+                        return operation(Operation::Subtract, a as u64, b as u64).unwrap()
+                    }
+                };
+                Expected::plain(if truth { 0 } else { u8::MAX as u64 })
+            }
+
+            /// Whether a jump is taken after comparing `a` with `b`
+            pub fn jump(jump: JumpType, a: u64, b: u64) -> bool {
+                let (a, b) = (a as $unsigned, b as $unsigned);
+                let (sa, sb) = (a as $signed, b as $signed);
+                let difference = a.wrapping_sub(b);
+                match jump {
+                    JumpType::Zero | JumpType::Equal => a == b,
+                    JumpType::NotZero | JumpType::NotEqual => a != b,
+                    JumpType::Greater => sa > sb,
+                    JumpType::GreaterEqual => sa >= sb,
+                    JumpType::Above => a > b,
+                    JumpType::AboveEqual => a >= b,
+                    JumpType::Lesser => sa < sb,
+                    JumpType::LessEqual => sa <= sb,
+                    JumpType::Below => a < b,
+                    JumpType::BelowEqual => a <= b,
+                    JumpType::Overflow => sa.checked_sub(sb).is_none(),
+                    JumpType::NotOverflow => sa.checked_sub(sb).is_some(),
+                    JumpType::Signed => (difference as $signed) < 0,
+                    JumpType::NotSigned => (difference as $signed) >= 0,
+                    JumpType::Parity => (difference as u8).count_ones() % 2 == 0,
+                    JumpType::NotParity => (difference as u8).count_ones() % 2 == 1,
+                }
+            }
+
+            pub fn check(a: u64, b: u64) {
+                for &operation in &OPERATIONS {
+                    let mut flags = Flags::new();
+                    let actual = operation.perform_op(
+                        ArithmeticMode::Wrapping,
+                        &mut flags,
+                        immediate(a),
+                        immediate(b),
+                    );
+                    let context = format!("{:?} {} {}", operation, a as $unsigned, b as $unsigned);
+                    match self::operation(operation, a, b) {
+                        None => assert!(matches!(actual, Err(Fault::DivideByZero)), "{}", context),
+                        Some(expected) => assert_flags(actual, &flags, expected, &context),
+                    }
+                }
+                for &comparison in &COMPARISONS {
+                    let mut flags = Flags::new();
+                    let actual = comparison.perform_op(&mut flags, immediate(a), immediate(b));
+                    let context = format!("{:?} {} {}", comparison, a as $unsigned, b as $unsigned);
+                    let expected = self::comparison(comparison, a, b);
+                    assert_flags(actual, &flags, expected, &context);
+                }
+                let mut flags = Flags::new();
+                ComparisonOperation::Compare
+                    .perform_op(&mut flags, immediate(a), immediate(b))
+                    .unwrap();
+                for &jump in &JUMPS {
+                    assert_eq!(
+                        jump.is_taken(&flags),
+                        self::jump(jump, a, b),
+                        "{:?} after comparing {} with {}",
+                        jump,
+                        a as $unsigned,
+                        b as $unsigned
+                    );
+                }
+            }
+
+            /// Checks the modes of the operations that can overflow against the wrapped result
+            pub fn check_modes(a: u64, b: u64) {
+                for &operation in &[Operation::Add, Operation::Subtract, Operation::Multiply] {
+                    let expected = self::operation(operation, a, b).unwrap();
+                    let checked = operation.perform_op(
+                        ArithmeticMode::Checked,
+                        &mut Flags::new(),
+                        immediate(a),
+                        immediate(b),
+                    );
+                    let saturated = operation
+                        .perform_op(
+                            ArithmeticMode::Saturating,
+                            &mut Flags::new(),
+                            immediate(a),
+                            immediate(b),
+                        )
+                        .unwrap()
+                        .into_u64();
+                    let (a, b) = (a as $unsigned, b as $unsigned);
+                    let (native_checked, native_saturated) = match operation {
+                        Operation::Add => (a.checked_add(b), a.saturating_add(b)),
+                        Operation::Subtract => (a.checked_sub(b), a.saturating_sub(b)),
+                        _ => (a.checked_mul(b), a.saturating_mul(b)),
+                    };
+                    assert_eq!(native_checked.is_none(), expected.carry);
+                    match native_checked {
+                        Some(value) => assert_eq!(checked.unwrap().into_u64(), U64(value as u64)),
+                        None => assert!(matches!(checked, Err(Fault::ArithmeticOverflow))),
+                    }
+                    assert_eq!(saturated, U64(native_saturated as u64));
+                }
+            }
+        }
+    };
+}
+
+reference!(byte, u8, i8, U8);
+reference!(word, u16, i16, U16);
+reference!(double_word, u32, i32, U32);
+reference!(quad_word, u64, i64, U64);
+
+fn assert_flags(
+    actual: Result<Immediate, Fault>,
+    flags: &Flags,
+    expected: Expected,
+    context: &str,
+) {
+    let actual = actual.unwrap_or_else(|fault| panic!("{}: {:?}", context, fault));
+    let bits = match actual {
+        U8(_) => 8,
+        U16(_) => 16,
+        U32(_) => 32,
+        _ => 64,
+    };
+    let value = match actual.clone().into_u64() {
+        U64(value) => value,
+        _ => unreachable!(),
+    };
+    assert_eq!(value, expected.value, "{}: result", context);
+    assert_eq!(flags.zero, expected.value == 0, "{}: zero", context);
+    assert_eq!(
+        flags.sign,
+        expected.value >> (bits - 1) & 1 == 1,
+        "{}: sign",
+        context
+    );
+    let parity = (expected.value as u8).count_ones().is_multiple_of(2);
+    assert_eq!(flags.parity, parity, "{}: parity", context);
+    assert_eq!(flags.carry, expected.carry, "{}: carry", context);
+    assert_eq!(flags.overflow, expected.overflow, "{}: overflow", context);
+}
+
+/// Values around every boundary of a type that is `bits` wide, and a deterministic spread of
+/// others
+fn samples(bits: u32) -> Vec<u64> {
+    let max = u64::MAX >> (64 - bits);
+    let sign = 1 << (bits - 1);
+    let mut samples = vec![
+        0,
+        1,
+        2,
+        3,
+        7,
+        8,
+        9,
+        bits as u64 - 1,
+        bits as u64,
+        bits as u64 + 1,
+    ];
+    samples.extend(&[sign - 1, sign, sign + 1, max - 1, max]);
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    for _ in 0..48 {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        samples.push(state & max);
+    }
+    samples
+}
+
+#[test]
+fn u8_exhaustive() {
+    for a in 0..=u8::MAX as u64 {
+        for b in 0..=u8::MAX as u64 {
+            byte::check(a, b);
+            byte::check_modes(a, b);
+        }
+    }
+}
+
+#[test]
+fn u16_sampled() {
+    let samples = samples(16);
+    for &a in &samples {
+        for &b in &samples {
+            word::check(a, b);
+            word::check_modes(a, b);
+        }
+    }
+}
+
+#[test]
+fn u32_sampled() {
+    let samples = samples(32);
+    for &a in &samples {
+        for &b in &samples {
+            double_word::check(a, b);
+            double_word::check_modes(a, b);
+        }
+    }
+}
+
+#[test]
+fn u64_sampled() {
+    let samples = samples(64);
+    for &a in &samples {
+        for &b in &samples {
+            quad_word::check(a, b);
+            quad_word::check_modes(a, b);
+        }
+    }
+}
+
+#[test]
+fn float_flags() {
+    let mut flags = Flags::new();
+    let ret = Operation::Subtract
+        .perform_op(
+            ArithmeticMode::Wrapping,
+            &mut flags,
+            Immediate::Double(1.0),
+            Immediate::Double(2.5),
+        )
+        .unwrap();
+    assert_eq!(ret, Immediate::Double(-1.5));
+    assert!(flags.sign && !flags.zero && !flags.parity && !flags.carry && !flags.overflow);
+
+    Operation::Divide
+        .perform_op(
+            ArithmeticMode::Wrapping,
+            &mut flags,
+            Immediate::Double(0.0),
+            Immediate::Double(0.0),
+        )
+        .unwrap();
+    assert!(flags.parity);
+}
@@ -0,0 +1,90 @@
+use virtual_machine::instruction_set::Immediate::U32;
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Instruction, Literal, Operation};
+use virtual_machine::memory::Scope::Local;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+/// Sends `value` over the channel in `Caller 0` twice, yielding in between
+fn sender(value: u32) -> Vec<Instruction> {
+    vec![
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PushVal(U32(value)),
+        Send,
+        Yield,
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PushVal(U32(value)),
+        Send,
+        PushVal(U32(0)),
+        Halt,
+    ]
+}
+
+#[test]
+fn join_spawned_context() {
+    let instructions = vec![
+        DeclareVar("x".to_string(), Local),
+        PushVal(U32(1)),
+        SaveVar("x".to_string()),
+        Spawn(8),
+        Join,
+        GetVar("x".to_string()),
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        Halt,
+        DeclareVar("x".to_string(), Local),
+        PushVal(U32(41)),
+        SaveVar("x".to_string()),
+        GetVar("x".to_string()),
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        42
+    );
+}
+
+#[test]
+fn round_robin_over_channel() {
+    let mut instructions = vec![
+        MakeChannel,
+        PopTo(Literal::Register(Caller, 0)),
+        Spawn(28),
+        Pop,
+        Spawn(37),
+        Pop,
+        PushVal(U32(0)),
+    ];
+    for _ in 0..4 {
+        instructions.extend(vec![
+            PushVal(U32(10)),
+            PerformOperation(Operation::Multiply, ArithmeticMode::Wrapping),
+            Push {
+                src: Literal::Register(Caller, 0),
+            },
+            Receive,
+            PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        ]);
+    }
+    instructions.push(Halt);
+    instructions.extend(sender(1));
+    instructions.extend(sender(2));
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        1212
+    );
+}
+
+#[test]
+fn deadlock() {
+    let instructions = vec![MakeChannel, Receive, Halt];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::Deadlock)));
+
+    let instructions = vec![Spawn(3), Join, Halt, MakeChannel, Receive, Halt];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::Deadlock)));
+}
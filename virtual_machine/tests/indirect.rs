@@ -0,0 +1,74 @@
+use virtual_machine::instruction_set::Immediate::{Function, USize, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::{ArithmeticMode, Literal, Operation};
+use virtual_machine::resolution::functions::FunctionBuilder;
+use virtual_machine::resolution::FullIdentifier;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+#[test]
+fn function_pointer() {
+    let answer = FunctionBuilder::with_name(FullIdentifier::from("answer"))
+        .no_parameters()
+        .with_instructions(vec![
+            Jump(2),
+            PushVal(U32(0)),
+            Ret(Some(Literal::Immediate(U32(42)))),
+        ])
+        .build();
+    let instructions = vec![PushVal(Function(answer)), CallIndirect, Halt];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        42
+    );
+}
+
+#[test]
+fn call_function() {
+    let seven = FunctionBuilder::with_name(FullIdentifier::from("seven"))
+        .no_parameters()
+        .with_instructions(vec![Jump(2), Halt, Ret(Some(Literal::Immediate(U32(7))))])
+        .build();
+    let instructions = vec![
+        CallFunction(seven.clone()),
+        CallFunction(seven),
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        14
+    );
+}
+
+#[test]
+fn jump_table() {
+    for (index, expected) in [10, 20, 30].iter().enumerate() {
+        let instructions = vec![
+            PushVal(USize(index * 2)),
+            PushVal(USize(4)),
+            PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+            JumpIndirect,
+            PushVal(U32(10)),
+            Halt,
+            PushVal(U32(20)),
+            Halt,
+            PushVal(U32(30)),
+            Halt,
+        ];
+        assert_eq!(
+            VirtualMachine::headless_execute(instructions, 0).unwrap(),
+            *expected
+        );
+    }
+}
+
+#[test]
+fn invalid_targets() {
+    let instructions = vec![PushVal(USize(100)), JumpIndirect, Halt];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::InvalidJumpTarget(100))));
+
+    let instructions = vec![PushVal(U32(1)), CallIndirect, Halt];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::NotCallable)));
+}
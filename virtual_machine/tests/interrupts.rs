@@ -0,0 +1,122 @@
+use std::thread;
+use std::time::Duration;
+
+use virtual_machine::instruction_set::Immediate::{USize, U16, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{
+    ArithmeticMode, ComparisonOperation, Instruction, JumpType, Literal, Operation,
+};
+use virtual_machine::interrupts::{TIMER_INTERRUPT, TRAP_INTERRUPT};
+use virtual_machine::registers::SpecialRegister;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+const HANDLER: usize = 9;
+
+/// Spins until an interrupt handler sets `Caller 0`, then halts with it
+fn wait_program() -> Vec<Instruction> {
+    vec![
+        EnableInterrupts,
+        PushVal(USize(0)),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Compare(ComparisonOperation::Compare),
+        Pop,
+        ConditionalJump(JumpType::Zero, 1),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+        Move {
+            dest: Literal::Register(Caller, 0),
+            src: Literal::Immediate(USize(7)),
+        },
+        ReturnFromInterrupt,
+    ]
+}
+
+#[test]
+fn raised_interrupt_runs_handler() {
+    let mut vm = VirtualMachine::new();
+    vm.set_interrupt_handler(5, HANDLER);
+    vm.interrupt_handle().raise(5).unwrap();
+    assert_eq!(vm.execute(wait_program(), 0).unwrap(), 7);
+}
+
+#[test]
+fn interrupt_from_another_thread() {
+    let mut vm = VirtualMachine::new();
+    vm.set_interrupt_handler(5, HANDLER);
+    let handle = vm.interrupt_handle();
+    let host = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        handle.raise(5).unwrap();
+    });
+    assert_eq!(vm.execute(wait_program(), 0).unwrap(), 7);
+    host.join().unwrap();
+}
+
+#[test]
+fn timer_interrupts() {
+    let mut vm = VirtualMachine::new();
+    vm.set_interrupt_handler(TIMER_INTERRUPT, HANDLER);
+    vm.set_timer(Some(20));
+    assert_eq!(vm.execute(wait_program(), 0).unwrap(), 7);
+}
+
+#[test]
+fn disabled_interrupts_stay_pending() {
+    let mut vm = VirtualMachine::new();
+    vm.set_interrupt_handler(5, 3);
+    let handle = vm.interrupt_handle();
+    handle.raise(5).unwrap();
+    let instructions = vec![
+        DisableInterrupts,
+        PushVal(U32(0)),
+        Halt,
+        PushVal(U32(1)),
+        Halt,
+    ];
+    assert_eq!(vm.execute(instructions, 0).unwrap(), 0);
+    assert!(handle.is_pending(5));
+}
+
+#[test]
+fn trap_after_every_instruction() {
+    let mut vm = VirtualMachine::new();
+    vm.set_interrupt_handler(TRAP_INTERRUPT, 9);
+    let instructions = vec![
+        PushVal(U16(1 << 8)),
+        PopTo(Literal::special(SpecialRegister::Flags)),
+        Nop,
+        Nop,
+        PushVal(U16(0)),
+        PopTo(Literal::special(SpecialRegister::Flags)),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PushVal(USize(1)),
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        ReturnFromInterrupt,
+    ];
+    assert_eq!(vm.execute(instructions, 0).unwrap(), 4);
+}
+
+#[test]
+fn unhandled_interrupt_faults() {
+    let mut vm = VirtualMachine::new();
+    vm.interrupt_handle().raise(2).unwrap();
+    let instructions = vec![EnableInterrupts, PushVal(U32(0)), Halt];
+    assert!(matches!(
+        vm.execute(instructions, 0),
+        Err(Fault::UnhandledInterrupt(2))
+    ));
+}
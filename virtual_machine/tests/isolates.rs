@@ -0,0 +1,72 @@
+use virtual_machine::instruction_set::Immediate::{Pointer, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Instruction, JumpType, Literal, Operation};
+use virtual_machine::isolate::{Isolate, IsolateError, Program, SharedHeap, SharedValue};
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+/// Increments the shared `count` `times` times, retrying whenever another isolate got there first
+fn increment_program(times: u32) -> Vec<Instruction> {
+    vec![
+        Move {
+            dest: Literal::Register(Caller, 1),
+            src: Literal::Immediate(U32(times)),
+        },
+        SharedLoad("count".to_string()),
+        Push { src: Literal::Peak },
+        PushVal(U32(1)),
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        SharedCompareSwap("count".to_string()),
+        Pop,
+        ConditionalJump(JumpType::NotZero, 1),
+        PushVal(U32(1)),
+        Push {
+            src: Literal::Register(Caller, 1),
+        },
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 1)),
+        ConditionalJump(JumpType::NotZero, 1),
+        PushVal(U32(0)),
+        Halt,
+    ]
+}
+
+#[test]
+fn isolates_share_heap() {
+    let heap = SharedHeap::new();
+    heap.store("count", SharedValue::U32(0));
+    let program = Program::new(increment_program(200), vec![]).unwrap();
+
+    let isolates: Vec<Isolate> = (0..4).map(|_| Isolate::spawn(&program, 0, &heap)).collect();
+    for isolate in isolates {
+        assert_eq!(isolate.join().unwrap(), 0);
+    }
+    assert_eq!(heap.load("count"), Some(SharedValue::U32(800)));
+}
+
+#[test]
+fn pointers_are_not_shareable() {
+    let instructions = vec![PushVal(Pointer(std::ptr::null_mut())), Halt];
+    assert!(matches!(
+        Program::new(instructions, vec![]),
+        Err(Fault::NotShareable)
+    ));
+
+    let instructions = vec![
+        PushVal(U32(1)),
+        Heapify,
+        SharedStore("pointer".to_string()),
+        PushVal(U32(0)),
+        Halt,
+    ];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::NotShareable)));
+}
+
+#[test]
+fn isolate_faults() {
+    let heap = SharedHeap::new();
+    let program = Program::new(vec![SharedLoad("missing".to_string()), Halt], vec![]).unwrap();
+    let result = Isolate::spawn(&program, 0, &heap).join();
+    assert!(matches!(result, Err(IsolateError::Faulted(_))));
+}
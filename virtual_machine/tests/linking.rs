@@ -0,0 +1,99 @@
+use std::iter::FromIterator;
+
+use virtual_machine::identifier;
+use virtual_machine::instruction_set::Immediate::{USize, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Literal, Operation};
+use virtual_machine::linking::{LinkError, Module, ModuleBuilder};
+use virtual_machine::resolution::FullIdentifier;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+/// Exports `math::square`, which squares `Caller 0` in place, and the global `math::offset`
+fn math_module() -> Module {
+    ModuleBuilder::with_namespace(FullIdentifier::from("math"))
+        .with_instructions(vec![
+            Halt,
+            Jump(2),
+            Push {
+                src: Literal::Register(Caller, 0),
+            },
+            Push {
+                src: Literal::Register(Caller, 0),
+            },
+            PerformOperation(Operation::Multiply, ArithmeticMode::Wrapping),
+            PopTo(Literal::Register(Caller, 0)),
+            Ret(None),
+        ])
+        .with_function("square", 1)
+        .with_global("offset", Some(USize(3)))
+        .build()
+}
+
+/// Exports `app::start`, which halts with the square of five plus `math::offset`
+fn app_module() -> Module {
+    ModuleBuilder::with_namespace(FullIdentifier::from("app"))
+        .with_instructions(vec![
+            Move {
+                dest: Literal::Register(Caller, 0),
+                src: Literal::Immediate(USize(5)),
+            },
+            CallSymbol(identifier!(math::square)),
+            Push {
+                src: Literal::Register(Caller, 0),
+            },
+            GetVar("math::offset".to_string()),
+            PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+            Coerce { dest_type: U32(0) },
+            Halt,
+        ])
+        .with_function("start", 0)
+        .build()
+}
+
+#[test]
+fn calls_resolve_lazily() {
+    let mut vm = VirtualMachine::new();
+    assert_eq!(vm.load_module(&app_module()), Ok(0));
+    assert_eq!(vm.load_module(&math_module()), Ok(7));
+    assert_eq!(vm.address_of(&identifier!(math::square)), Some(8));
+    assert_eq!(vm.execute_symbol(&identifier!(app::start)).unwrap(), 28);
+    assert_eq!(vm.execute_symbol(&identifier!(app::start)).unwrap(), 28);
+}
+
+#[test]
+fn calls_resolve_at_load_time() {
+    let mut vm = VirtualMachine::new();
+    vm.load_module(&app_module()).unwrap();
+    assert_eq!(
+        vm.link(),
+        Err(vec![LinkError::UnresolvedSymbol(identifier!(math::square))])
+    );
+    vm.load_module(&math_module()).unwrap();
+    assert_eq!(vm.link(), Ok(()));
+    assert_eq!(vm.execute_symbol(&identifier!(app::start)).unwrap(), 28);
+}
+
+#[test]
+fn unresolved_symbol_faults() {
+    let mut vm = VirtualMachine::new();
+    vm.load_module(&app_module()).unwrap();
+    assert!(matches!(
+        vm.execute_symbol(&identifier!(app::start)),
+        Err(Fault::UnresolvedSymbol(symbol)) if symbol == identifier!(math::square)
+    ));
+    assert!(matches!(
+        vm.execute_symbol(&identifier!(app::missing)),
+        Err(Fault::UnresolvedSymbol(_))
+    ));
+}
+
+#[test]
+fn duplicate_symbols() {
+    let mut vm = VirtualMachine::new();
+    vm.load_module(&math_module()).unwrap();
+    assert_eq!(
+        vm.load_module(&math_module()),
+        Err(LinkError::DuplicateSymbol(identifier!(math::square)))
+    );
+}
@@ -0,0 +1,126 @@
+use virtual_machine::calling_convention::CallingConvention;
+use virtual_machine::instruction_set::Immediate::{USize, U16, U32, U8};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Literal, Operation};
+use virtual_machine::registers::SpecialRegister;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+#[test]
+fn computed_jump() {
+    let instructions = vec![
+        Push {
+            src: Literal::special(SpecialRegister::ProgramCounter),
+        },
+        PushVal(USize(6)),
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        PopTo(Literal::special(SpecialRegister::ProgramCounter)),
+        PushVal(U32(1)),
+        Halt,
+        PushVal(U32(2)),
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        2
+    );
+}
+
+#[test]
+fn stack_pointer() {
+    let instructions = vec![
+        PushVal(U32(9)),
+        PushVal(U32(1)),
+        PushVal(U32(2)),
+        Push {
+            src: Literal::special(SpecialRegister::StackPointer),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        3
+    );
+
+    let instructions = vec![
+        PushVal(U32(9)),
+        PushVal(U32(1)),
+        PushVal(U32(2)),
+        Move {
+            dest: Literal::special(SpecialRegister::StackPointer),
+            src: Literal::Immediate(USize(1)),
+        },
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        9
+    );
+}
+
+#[test]
+fn flags_word() {
+    let instructions = vec![
+        PushVal(U8(3)),
+        PushVal(U8(3)),
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        Push {
+            src: Literal::special(SpecialRegister::Flags),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ];
+    let word = VirtualMachine::headless_execute(instructions, 0).unwrap();
+    assert_ne!(word & 1 << 6, 0);
+
+    let instructions = vec![
+        Move {
+            dest: Literal::special(SpecialRegister::Flags),
+            src: Literal::Immediate(U16(0x0841)),
+        },
+        Push {
+            src: Literal::special(SpecialRegister::Flags),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ];
+    assert_eq!(
+        VirtualMachine::headless_execute(instructions, 0).unwrap(),
+        0x0841
+    );
+}
+
+#[test]
+fn frame_pointer() {
+    let instructions = vec![
+        Push {
+            src: Literal::special(SpecialRegister::FramePointer),
+        },
+        PopTo(Literal::Register(Caller, 0)),
+        Ret(None),
+        PushVal(U32(5)),
+        Call(0),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ];
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Preserving);
+    assert_eq!(vm.execute(instructions.clone(), 3).unwrap(), 1);
+    // Unchecked calls have frames too
+    let mut vm = VirtualMachine::new();
+    assert_eq!(vm.execute(instructions, 3).unwrap(), 1);
+
+    let instructions = vec![
+        Move {
+            dest: Literal::special(SpecialRegister::FramePointer),
+            src: Literal::Immediate(USize(0)),
+        },
+        Halt,
+    ];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::ReadOnlyRegister(2))));
+}
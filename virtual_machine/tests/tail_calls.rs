@@ -0,0 +1,135 @@
+use virtual_machine::analysis::verifier::Verifier;
+use virtual_machine::calling_convention::CallingConvention;
+use virtual_machine::instruction_set::Immediate::{USize, U32};
+use virtual_machine::instruction_set::Instruction::*;
+use virtual_machine::instruction_set::RegisterType::Caller;
+use virtual_machine::instruction_set::{ArithmeticMode, Instruction, JumpType, Literal, Operation};
+use virtual_machine::registers::SpecialRegister;
+use virtual_machine::resolution::functions::FunctionBuilder;
+use virtual_machine::resolution::FullIdentifier;
+use virtual_machine::vm::{Fault, VirtualMachine};
+
+const START: usize = 18;
+
+/// Sums `1..=n` by tail recursion, with `n` in `Caller 0` and the running total in `Caller 1`.
+/// The depth of the value stack when the recursion ends is left in `Caller 2`.
+fn sum_program(n: usize) -> Vec<Instruction> {
+    vec![
+        Enter,
+        PushVal(USize(1)),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        Pop,
+        ConditionalJump(JumpType::Below, 15),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Push {
+            src: Literal::Register(Caller, 1),
+        },
+        PerformOperation(Operation::Add, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 1)),
+        PushVal(USize(1)),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        PerformOperation(Operation::Subtract, ArithmeticMode::Wrapping),
+        PopTo(Literal::Register(Caller, 0)),
+        TailCall(0),
+        Move {
+            dest: Literal::Register(Caller, 2),
+            src: Literal::special(SpecialRegister::StackPointer),
+        },
+        Exit,
+        Ret(None),
+        Move {
+            dest: Literal::Register(Caller, 0),
+            src: Literal::Immediate(USize(n)),
+        },
+        Move {
+            dest: Literal::Register(Caller, 1),
+            src: Literal::Immediate(USize(0)),
+        },
+        Call(0),
+        Push {
+            src: Literal::Register(Caller, 1),
+        },
+        Coerce { dest_type: U32(0) },
+        Halt,
+    ]
+}
+
+#[test]
+fn tail_recursion_reuses_frame() {
+    let n = 10_000;
+    let instructions = sum_program(n);
+    assert_eq!(Verifier::new(START).verify(&instructions), Ok(()));
+
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Verifying);
+    let result = vm.execute(instructions, START);
+    assert_eq!(result.unwrap() as usize, n * (n + 1) / 2);
+    assert!(matches!(vm.get_register(Caller, 2), Some(USize(1))));
+}
+
+#[test]
+fn tail_call_function() {
+    let three = FunctionBuilder::with_name(FullIdentifier::from("three"))
+        .no_parameters()
+        .with_instructions(vec![
+            Enter,
+            Move {
+                dest: Literal::Register(Caller, 0),
+                src: Literal::Immediate(U32(3)),
+            },
+            Exit,
+            Ret(None),
+        ])
+        .build();
+    let forward = FunctionBuilder::with_name(FullIdentifier::from("forward"))
+        .no_parameters()
+        .with_instructions(vec![Enter, TailCallFunction(three)])
+        .build();
+    let instructions = vec![
+        CallFunction(forward),
+        Push {
+            src: Literal::Register(Caller, 0),
+        },
+        Halt,
+    ];
+    let mut vm = VirtualMachine::new();
+    vm.set_calling_convention(CallingConvention::Verifying);
+    assert_eq!(vm.execute(instructions, 0).unwrap(), 3);
+}
+
+#[test]
+fn tail_call_without_caller() {
+    let instructions = vec![TailCall(1), PushVal(U32(0)), Halt];
+    let result = VirtualMachine::headless_execute(instructions, 0);
+    assert!(matches!(result, Err(Fault::InvalidReturn)));
+}
+
+#[test]
+fn tail_call_without_scope() {
+    let instructions = vec![TailCall(1), Ret(None), Call(0), PushVal(U32(0)), Halt];
+    let result = VirtualMachine::headless_execute(instructions, 2);
+    assert!(matches!(result, Err(Fault::UnbalancedScope)));
+}
+
+#[test]
+fn tail_call_above_frame() {
+    let instructions = vec![
+        Enter,
+        PushVal(USize(4)),
+        TailCall(3),
+        Exit,
+        Ret(None),
+        Call(0),
+        PushVal(U32(0)),
+        Halt,
+    ];
+    let result = VirtualMachine::headless_execute(instructions, 5);
+    assert!(matches!(result, Err(Fault::UnbalancedFrame)));
+}